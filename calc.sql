@@ -1,8 +1,11 @@
 /*
 Installation:
     PGPASSWORD=repu123 psql -U reputation -f t1.sql -1
+    or `reputation-aggregator-mock install-scoring`
     
     Subsequent execution overwrites results of the previous execution.
+    Requires server migrations to be applied first, agreement weights, retention aggregates
+    and moderation tables are read from the public schema.
 
 Refresh aggregates:
     SELECT calc.refresh_all()
@@ -10,16 +13,41 @@ Refresh aggregates:
     This takes ~ 1s for ~70000 rows in agreement_status.
     There are no clever optimalizations here - everything is recalculated.
     
+Refresh ranking (after trust scores are stored, and on bans and score overrides)
+    SELECT calc.refresh_ranking(5, 3)  # minimum agreements and counterparties
+
 Get score
     SELECT calc.standard_score('P', '0xabc123...')  # provider
     SELECT calc.standard_score('R', '0xabc123...')  # requestor
 
+Get segmented score (segment_type is one of payment_platform, network, subnet)
+    SELECT standard_score FROM calc.segment_score
+    WHERE (segment_type, segment, role_id, node_id) = ('network', 'mainnet', 'P', '0xabc123...')
+
+Get score with evidence, rank and percentile (segment_type is '' overall or 'trust')
+    SELECT * FROM calc.scored_node('P', '0xabc123...')
+    SELECT * FROM calc.scored_node('P', '0xabc123...', 'network', 'mainnet')
+
 */
 
 DROP SCHEMA IF EXISTS calc CASCADE;
 CREATE SCHEMA calc;
 SET search_path TO calc;
 
+-- test token platforms (eg. erc20-rinkeby-tglm, erc20-mumbai-tglm) are testnets
+CREATE FUNCTION network(payment_platform TEXT) RETURNS TEXT
+LANGUAGE SQL IMMUTABLE
+AS $fff$
+	SELECT	CASE	WHEN $1 IS NULL
+			THEN NULL
+			WHEN $1 LIKE '%-tglm'
+			  OR $1 ~ '(rinkeby|goerli|mumbai|sepolia|holesky)'
+			THEN 'testnet'
+			ELSE 'mainnet'
+		END;
+$fff$
+;
+
 -- Per-agreement aggregate
 CREATE MATERIALIZED VIEW agreement AS
 WITH
@@ -33,10 +61,17 @@ prefiltered_agreement_status AS (
                 s.created_ts,
                 s.updated_ts,
                 s.reported_ts,
-                d.peer_id
+                d.peer_id,
+                d.payment_platform,
+                d.subnet
         FROM    public.agreement_status  s
         JOIN    public.agreement_details d
-            ON  s.agreement_id = d.agreement_id
+            ON  (s.role_id, s.node_id, s.agreement_id) = (d.role_id, d.node_id, d.agreement_id)
+        -- moderation: quarantined agreements and reports of banned nodes are ignored
+        WHERE   s.agreement_id NOT IN (SELECT agreement_id FROM public.agreement_quarantine)
+          AND   s.node_id NOT IN (
+                    SELECT node_id FROM public.node_moderation WHERE status = 'banned'
+                )
 ),
 provider AS (
     	SELECT 	*
@@ -58,7 +93,9 @@ base_agg AS (
 		coalesce((p.requested + r.requested)/2, p.requested, r.requested) AS requested,
 		coalesce((p.accepted  + r.accepted) /2, p.accepted,  r.accepted)  AS accepted,
 		coalesce((p.confirmed + r.confirmed)/2, p.confirmed, r.confirmed) AS confirmed,
-		greatest(p.updated_ts, r.updated_ts) AS updated_ts
+		greatest(p.updated_ts, r.updated_ts) AS updated_ts,
+		coalesce(p.payment_platform, r.payment_platform) AS payment_platform,
+		coalesce(p.subnet, r.subnet) AS subnet
 	FROM	provider  p
 	FULL
 	OUTER
@@ -93,6 +130,8 @@ array_scores AS (
 	--	NOTE: the only purpose of this aggregate is to have provider and requestor scores
 	--	      next to each other, array is unpacked later
 	SELECT	at.*,
+		-- agreements flagged by the sybil/collusion analysis count less (see src/analysis.rs)
+		coalesce(f.weight, 1) AS weight,
 		CASE
 			WHEN agreement_result = 'PAID'
 			THEN ARRAY[accepted, accepted]
@@ -106,27 +145,62 @@ array_scores AS (
 			ELSE ARRAY[0, 0]
 		END AS scores
 	FROM	agreement_type at
+	LEFT
+	JOIN	public.agreement_flag f
+	  ON	at.agreement_id = f.agreement_id
 )
 SELECT	a.agreement_id, a.p_id, a.r_id, a.requested, a.accepted, a.confirmed, a.agreement_result, a.updated_ts,
-	scores[1] AS p_score,
-	scores[2] AS r_score
+	-- flags reduce what an agreement earns, never what it costs
+	CASE WHEN scores[1] > 0 THEN scores[1] * a.weight ELSE scores[1] END AS p_score,
+	CASE WHEN scores[2] > 0 THEN scores[2] * a.weight ELSE scores[2] END AS r_score,
+	a.weight,
+	a.payment_platform,
+	a.subnet,
+	calc.network(a.payment_platform) AS network
 FROM	array_scores a
 ;
 
+-- Scores of live agreements and of agreements removed by the retention policy
+-- (see src/retention.rs), by node, counterparty and segment
+CREATE VIEW contribution AS
+SELECT	'P' AS role_id, a.p_id AS node_id, a.r_id AS counterparty,
+	a.payment_platform, a.network, a.subnet,
+	1::bigint AS agreements, a.p_score AS score, a.p_score * a.p_score AS score_sq
+FROM	calc.agreement a
+UNION ALL
+SELECT	'R', a.r_id, a.p_id,
+	a.payment_platform, a.network, a.subnet,
+	1, a.r_score, a.r_score * a.r_score
+FROM	calc.agreement a
+UNION ALL
+SELECT	'P', aa.p_id, aa.r_id,
+	nullif(aa.payment_platform, ''), calc.network(nullif(aa.payment_platform, '')),
+	nullif(aa.subnet, ''),
+	aa.agreements, aa.p_score, aa.p_score_sq
+FROM	public.agreement_archive aa
+UNION ALL
+SELECT	'R', aa.r_id, aa.p_id,
+	nullif(aa.payment_platform, ''), calc.network(nullif(aa.payment_platform, '')),
+	nullif(aa.subnet, ''),
+	aa.agreements, aa.r_score, aa.r_score_sq
+FROM	public.agreement_archive aa
+;
+
 CREATE MATERIALIZED VIEW node_score AS
 WITH
 raw_score AS (
-	SELECT	a.p_id		AS node_id,
-		'P' 		AS role_id,
-		sum(p_score)	AS raw_score
-	FROM	calc.agreement a
-	GROUP BY 1
-	UNION
-	SELECT	a.r_id		AS node_id,
-		'R'		AS role_id,
-		sum(r_score)	AS raw_score
-	FROM	calc.agreement a
-	GROUP BY 1
+	SELECT	s.node_id,
+		s.role_id,
+		sum(s.raw_score) AS raw_score
+	FROM	(
+		SELECT	c.node_id, c.role_id, c.score AS raw_score
+		FROM	calc.contribution c
+		UNION ALL
+		-- agreements removed by the retention policy before agreement_archive existed
+		SELECT	na.node_id, na.role_id, na.raw_score
+		FROM	public.node_archive na
+	) s
+	GROUP BY 1, 2
 ),
 agg_metrics AS (
 	SELECT	rs.role_id,
@@ -146,21 +220,224 @@ JOIN	agg_metrics 	am
 
 CREATE UNIQUE INDEX ON node_score (role_id, node_id);
 
+-- Same as node_score, but computed separately within every payment platform, network and subnet
+CREATE VIEW segmented_contribution AS
+SELECT	'payment_platform' AS segment_type, c.payment_platform AS segment, c.*
+FROM	calc.contribution c
+WHERE	c.payment_platform IS NOT NULL
+UNION ALL
+SELECT	'network', c.network, c.*
+FROM	calc.contribution c
+WHERE	c.network IS NOT NULL
+UNION ALL
+SELECT	'subnet', c.subnet, c.*
+FROM	calc.contribution c
+WHERE	c.subnet IS NOT NULL
+;
+
+CREATE MATERIALIZED VIEW segment_score AS
+WITH
+raw_score AS (
+	SELECT	sc.segment_type,
+		sc.segment,
+		sc.node_id,
+		sc.role_id,
+		sum(sc.score)	AS raw_score
+	FROM	calc.segmented_contribution sc
+	GROUP BY 1, 2, 3, 4
+),
+agg_metrics AS (
+	SELECT	rs.segment_type,
+		rs.segment,
+		rs.role_id,
+		avg(rs.raw_score) AS avg,
+		stddev_samp(rs.raw_score) AS stddev
+	FROM	raw_score rs
+	GROUP BY 1, 2, 3
+)
+SELECT	rs.segment_type,
+	rs.segment,
+	rs.node_id,
+	rs.role_id,
+	round(rs.raw_score, 8) AS raw_score,
+	round((rs.raw_score - am.avg) / nullif(am.stddev, 0), 8) AS standard_score
+FROM	raw_score 	rs
+JOIN	agg_metrics 	am
+    ON	(rs.segment_type, rs.segment, rs.role_id) = (am.segment_type, am.segment, am.role_id)
+;
+
+CREATE UNIQUE INDEX ON segment_score (segment_type, segment, role_id, node_id);
+
+-- Thresholds of calc.ranked_score, set by refresh_ranking()
+CREATE TABLE min_evidence (
+	agreements	bigint NOT NULL,
+	counterparties	bigint NOT NULL
+);
+
+INSERT INTO min_evidence VALUES (0, 0);
+
+-- Every score with the evidence behind it, ranked among the scores of the same role and segment
+-- with at least the minimum evidence. segment_type is '' for the overall score and 'trust' for
+-- the counterparty-weighted one (see src/trust.rs), segment is '' for both.
+-- Banned nodes are never ranked, nodes with an overridden score always are.
+--
+-- Confidence interval margin assumes independent agreement contributions: standard error
+-- of the raw score (a sum) is sqrt(n) * stddev(contribution), scaled like the score.
+-- Archived agreements only keep sums of their scores and squared scores, which is all the
+-- sample standard deviation needs.
+CREATE MATERIALIZED VIEW ranked_score AS
+WITH
+score AS (
+	SELECT	'' AS segment_type, '' AS segment, ns.role_id, ns.node_id, ns.raw_score, ns.standard_score
+	FROM	calc.node_score ns
+	UNION ALL
+	SELECT	ss.segment_type, ss.segment, ss.role_id, ss.node_id, ss.raw_score, ss.standard_score
+	FROM	calc.segment_score ss
+	UNION ALL
+	SELECT	'trust', '', ts.role_id, ts.node_id, ts.raw_score, ts.standard_score
+	FROM	public.trust_score ts
+),
+contribution AS (
+	SELECT	'' AS segment_type, '' AS segment, c.role_id, c.node_id, c.counterparty,
+		c.agreements, c.score, c.score_sq
+	FROM	calc.contribution c
+	UNION ALL
+	SELECT	'trust', '', c.role_id, c.node_id, c.counterparty, c.agreements, c.score, c.score_sq
+	FROM	calc.contribution c
+	UNION ALL
+	SELECT	sc.segment_type, sc.segment, sc.role_id, sc.node_id, sc.counterparty,
+		sc.agreements, sc.score, sc.score_sq
+	FROM	calc.segmented_contribution sc
+),
+-- agreements removed by retention before agreement_archive only count in the overall score
+legacy_archive AS (
+	SELECT	'' AS segment_type, '' AS segment, na.role_id, na.node_id, na.agreements, na.counterparties
+	FROM	public.node_archive na
+),
+evidence AS (
+	SELECT	c.segment_type, c.segment, c.role_id, c.node_id,
+		sum(c.agreements)::bigint AS agreements,
+		sqrt(greatest(sum(c.score_sq) - sum(c.score) ^ 2 / sum(c.agreements), 0)
+		     / nullif(sum(c.agreements) - 1, 0)) AS score_stddev
+	FROM	contribution c
+	GROUP BY 1, 2, 3, 4
+),
+counterparty AS (
+	SELECT	c.segment_type, c.segment, c.role_id, c.node_id,
+		count(DISTINCT c.counterparty) AS counterparties
+	FROM	(
+		SELECT	segment_type, segment, role_id, node_id, counterparty
+		FROM	contribution
+		UNION ALL
+		SELECT	segment_type, segment, role_id, node_id, unnest(counterparties)
+		FROM	legacy_archive
+	) c
+	GROUP BY 1, 2, 3, 4
+),
+spread AS (
+	SELECT	s.segment_type, s.segment, s.role_id,
+		stddev_samp(s.raw_score) AS raw_stddev
+	FROM	score s
+	GROUP BY 1, 2, 3
+),
+scored AS (
+	SELECT	s.segment_type,
+		s.segment,
+		s.role_id,
+		s.node_id,
+		coalesce(so.standard_score, s.standard_score) AS score,
+		coalesce(e.agreements, 0) + coalesce(la.agreements, 0) AS agreements,
+		coalesce(c.counterparties, 0) AS counterparties,
+		CASE	WHEN so.standard_score IS NULL
+			THEN (1.96 * e.score_stddev * sqrt(e.agreements) / nullif(sp.raw_stddev, 0))::float8
+		END AS margin,
+		nm.status IS NOT DISTINCT FROM 'banned' AS banned,
+		so.standard_score IS NOT NULL AS overridden,
+		nm.status IS DISTINCT FROM 'banned'
+		    AND (so.standard_score IS NOT NULL
+			 OR s.standard_score IS NOT NULL
+			    AND coalesce(e.agreements, 0) + coalesce(la.agreements, 0) >= me.agreements
+			    AND coalesce(c.counterparties, 0) >= me.counterparties) AS sufficient
+	FROM	score s
+	JOIN	spread sp
+	    ON	(sp.segment_type, sp.segment, sp.role_id) = (s.segment_type, s.segment, s.role_id)
+	LEFT
+	JOIN	evidence e
+	    ON	(e.segment_type, e.segment, e.role_id, e.node_id)
+		= (s.segment_type, s.segment, s.role_id, s.node_id)
+	LEFT
+	JOIN	counterparty c
+	    ON	(c.segment_type, c.segment, c.role_id, c.node_id)
+		= (s.segment_type, s.segment, s.role_id, s.node_id)
+	LEFT
+	JOIN	legacy_archive la
+	    ON	(la.segment_type, la.segment, la.role_id, la.node_id)
+		= (s.segment_type, s.segment, s.role_id, s.node_id)
+	-- operator overrides replace the overall standard score only
+	LEFT
+	JOIN	public.score_override so
+	    ON	(so.role_id, so.node_id) = (s.role_id, s.node_id) AND s.segment_type = ''
+	LEFT
+	JOIN	public.node_moderation nm
+	    ON	nm.node_id = s.node_id
+	CROSS
+	JOIN	calc.min_evidence me
+)
+SELECT	sc.*,
+	CASE	WHEN sufficient
+		THEN rank() OVER (PARTITION BY segment_type, segment, role_id, sufficient ORDER BY score DESC)
+	END AS rank,
+	CASE	WHEN sufficient
+		THEN 100 * percent_rank() OVER (PARTITION BY segment_type, segment, role_id, sufficient ORDER BY score)
+	END AS percentile
+FROM	scored sc
+;
+
+CREATE UNIQUE INDEX ON ranked_score (segment_type, segment, role_id, node_id);
+CREATE INDEX ON ranked_score (segment_type, segment, role_id, rank);
+
 CREATE FUNCTION refresh_all() RETURNS void
 LANGUAGE plpgsql
 AS $fff$
 BEGIN
 	REFRESH MATERIALIZED VIEW calc.agreement;
 	REFRESH MATERIALIZED VIEW calc.node_score;
+	REFRESH MATERIALIZED VIEW calc.segment_score;
 END;
 $fff$
 ;
 
+CREATE FUNCTION refresh_ranking(min_agreements bigint, min_counterparties bigint) RETURNS void
+LANGUAGE plpgsql
+AS $fff$
+BEGIN
+	UPDATE	calc.min_evidence
+	SET	agreements = min_agreements,
+		counterparties = min_counterparties;
+	REFRESH MATERIALIZED VIEW calc.ranked_score;
+END;
+$fff$
+;
+
+CREATE FUNCTION scored_node(
+	role_id CHAR(1),
+	node_id VARCHAR(42),
+	segment_type TEXT DEFAULT '',
+	segment TEXT DEFAULT ''
+) RETURNS SETOF calc.ranked_score
+LANGUAGE SQL STABLE
+AS $fff$
+	SELECT 	*
+	FROM	calc.ranked_score rs
+	WHERE	(rs.segment_type, rs.segment, rs.role_id, rs.node_id) = ($3, $4, $1, $2);
+$fff$
+;
+
+-- Overall standard score, or the one set by an operator
 CREATE FUNCTION standard_score(role_id CHAR(1), node_id VARCHAR(42)) RETURNS numeric
 LANGUAGE SQL
 AS $fff$
-	SELECT 	standard_score
-	FROM	calc.node_score ns
-	WHERE	(ns.role_id, ns.node_id) = ($1, $2);
+	SELECT 	score
+	FROM	calc.scored_node($1, $2);
 $fff$
 ;
@@ -1,20 +1,19 @@
-use crate::{AgreementInfo, ReportResult, Status};
+use crate::{
+    AgreementInfo, AgreementRole, ApiVersion, ApiVersions, ErrorCode, ErrorResponse, ReportResult,
+    ReputationEvent, Status,
+};
 
 #[cfg(feature = "client-old")]
 use awc_old as awc;
 
 use awc::error::SendRequestError;
+use futures::prelude::*;
+use std::time::Duration;
 use thiserror::Error;
 use ya_client_model::NodeId;
 
-#[derive(Clone)]
-pub enum AgreementRole {
-    Provider,
-    Requestor,
-}
-
 impl AgreementRole {
-    fn as_path(&self) -> &str {
+    fn as_path(&self) -> &'static str {
         match self {
             Self::Provider => "/provider",
             Self::Requestor => "/requestor",
@@ -35,6 +34,7 @@ impl AgreementRole {
 pub struct RepuAggrClient {
     client: awc::Client,
     base_url: String,
+    api_version: ApiVersion,
 }
 
 /// Error type.
@@ -46,6 +46,34 @@ pub enum RepuClientError {
     /// Server responds with communication error.
     #[error("{0}")]
     ProcessingError(String),
+    /// Server rejected the request with a structured error.
+    #[error("{message} ({code:?}, status {status})")]
+    ApiError {
+        status: u16,
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+impl RepuClientError {
+    /// Error code returned by the server, if the request reached it.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Self::ApiError { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    fn from_response_body(status: u16, body: &[u8]) -> Self {
+        match serde_json::from_slice::<ErrorResponse>(body) {
+            Ok(ErrorResponse { code, message }) => Self::ApiError {
+                status,
+                code,
+                message,
+            },
+            Err(_) => Self::ProcessingError(format!("bad response: {status}")),
+        }
+    }
 }
 
 /// A specialized Result type for client operations.
@@ -56,7 +84,53 @@ impl RepuAggrClient {
     pub fn with_url(base_url: impl Into<String>) -> Result<Self> {
         let client = awc::Client::new();
         let base_url = base_url.into();
-        Ok(RepuAggrClient { client, base_url })
+        Ok(RepuAggrClient {
+            client,
+            base_url,
+            api_version: ApiVersion::default(),
+        })
+    }
+
+    /// Uses given API version instead of [`ApiVersion::Legacy`].
+    pub fn with_api_version(mut self, api_version: ApiVersion) -> Self {
+        self.api_version = api_version;
+        self
+    }
+
+    pub fn api_version(&self) -> ApiVersion {
+        self.api_version
+    }
+
+    /// Asks the server for supported API versions and switches to the latest one supported
+    /// by both sides. Servers predating versioning only serve [`ApiVersion::Legacy`].
+    pub async fn negotiate_api_version(&mut self) -> Result<ApiVersion> {
+        let mut response = self
+            .client
+            .get(format!("{}/api", self.base_url))
+            .send()
+            .await?;
+        self.api_version = if response.status().is_success() {
+            let versions: ApiVersions = response
+                .json()
+                .await
+                .map_err(|e| RepuClientError::ProcessingError(e.to_string()))?;
+            if versions.versions.iter().any(|v| v == "v1") {
+                ApiVersion::V1
+            } else {
+                ApiVersion::Legacy
+            }
+        } else if response.status().as_u16() == 404 {
+            ApiVersion::Legacy
+        } else {
+            let status = response.status().as_u16();
+            let body = response.body().await.unwrap_or_default();
+            return Err(RepuClientError::from_response_body(status, &body));
+        };
+        Ok(self.api_version)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}{path}", self.base_url, self.api_version.prefix())
     }
 
     pub async fn agreement(
@@ -66,17 +140,15 @@ impl RepuAggrClient {
         agreement_id: &str,
         agreement: AgreementInfo,
     ) -> Result<()> {
-        let url = format!(
-            "{}{}/{node_id}/agreement/{agreement_id}",
-            self.base_url,
+        let url = self.url(&format!(
+            "{}/{node_id}/agreement/{agreement_id}",
             role.as_path()
-        );
-        let response = self.client.post(url).send_json(&agreement).await?;
+        ));
+        let mut response = self.client.post(url).send_json(&agreement).await?;
         if !response.status().is_success() {
-            return Err(RepuClientError::ProcessingError(format!(
-                "bad response: {}",
-                response.status()
-            )));
+            let status = response.status().as_u16();
+            let body = response.body().await.unwrap_or_default();
+            return Err(RepuClientError::from_response_body(status, &body));
         }
         Ok(())
     }
@@ -89,17 +161,82 @@ impl RepuAggrClient {
         status: Status,
     ) -> Result<ReportResult> {
         // TODO add checks
-        let base_url = &self.base_url;
         let role_path = role.as_path();
-        let url = format!("{base_url}{role_path}/{node_id}/agreement/{agreement_id}/status");
+        let url = self.url(&format!("{role_path}/{node_id}/agreement/{agreement_id}/status"));
         let mut response = self.client.post(url).send_json(&status).await?;
         if !response.status().is_success() {
-            return Err(RepuClientError::ProcessingError(format!(
-                "bad response: {}",
-                response.status()
-            )));
+            let status = response.status().as_u16();
+            let body = response.body().await.unwrap_or_default();
+            return Err(RepuClientError::from_response_body(status, &body));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| RepuClientError::ProcessingError(e.to_string()))
+    }
+
+    /// Subscribes to server-sent reputation events, optionally limited to given role and node.
+    pub async fn events(
+        &self,
+        role: Option<AgreementRole>,
+        node_id: Option<NodeId>,
+    ) -> Result<impl Stream<Item = Result<ReputationEvent>>> {
+        let mut query = Vec::new();
+        if let Some(role) = role {
+            query.push(format!("role={}", &role.as_path()[1..]));
+        }
+        if let Some(node_id) = node_id {
+            query.push(format!("nodeId={node_id}"));
+        }
+        let mut url = self.url("/events");
+        if !query.is_empty() {
+            url = format!("{url}?{}", query.join("&"));
+        }
+        let mut response = self
+            .client
+            .get(url)
+            .timeout(Duration::from_secs(365 * 24 * 3600))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let body = response.body().await.unwrap_or_default();
+            return Err(RepuClientError::from_response_body(status, &body));
         }
 
-        Ok(response.json().await.map_err(|e| RepuClientError::ProcessingError(e.to_string()))?)
+        Ok(stream::unfold(
+            (response.boxed_local(), Vec::<u8>::new()),
+            |(mut body, mut buffer)| async move {
+                loop {
+                    if let Some(pos) = buffer.windows(2).position(|w| w == b"\n\n") {
+                        let block: Vec<u8> = buffer.drain(..pos + 2).collect();
+                        let block = String::from_utf8_lossy(&block);
+                        let data = block
+                            .lines()
+                            .filter_map(|line| line.strip_prefix("data:"))
+                            .map(str::trim_start)
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        // comments (keep-alive) carry no data
+                        if data.is_empty() {
+                            continue;
+                        }
+                        let event = serde_json::from_str(&data)
+                            .map_err(|e| RepuClientError::ProcessingError(e.to_string()));
+                        return Some((event, (body, buffer)));
+                    }
+                    match body.next().await? {
+                        Ok(chunk) => buffer.extend_from_slice(&chunk),
+                        Err(e) => {
+                            return Some((
+                                Err(RepuClientError::ProcessingError(e.to_string())),
+                                (body, buffer),
+                            ))
+                        }
+                    }
+                }
+            },
+        ))
     }
 }
@@ -6,7 +6,6 @@ use bigdecimal::BigDecimal;
 use chrono::{DateTime, Utc};
 use derive_builder::*;
 use serde::{Deserialize, Serialize};
-use serde_json::Value as JsonValue;
 pub use ya_client_model::NodeId;
 
 /// Agreement status report.
@@ -27,18 +26,22 @@ pub use ya_client_model::NodeId;
 /// ```
 ///
 #[derive(Builder, Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[builder(setter(into), pattern = "owned")]
 #[non_exhaustive]
 #[serde(rename_all = "camelCase")]
 pub struct Status {
     /// The amount of money that provider has requested.
     #[builder(setter(into), default)]
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     pub requested: BigDecimal,
     /// The amount of money that requestor has accepted.
     #[builder(setter(into), default)]
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     pub accepted: BigDecimal,
     /// The amount of money the provider has confirmed that is paid.
     #[builder(setter(into), default)]
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     pub confirmed: BigDecimal,
     /// Event timestamp.
     #[builder(default = "Utc::now()")]
@@ -47,23 +50,103 @@ pub struct Status {
     #[builder(setter(into), default)]
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub payment_due_ts: Option<DateTime<Utc>>,
-    /// Reserved for future use
-    #[builder(setter(skip))]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub payment: Option<JsonValue>,
+    /// Debit note, invoice and payment level details.
+    #[builder(setter(into), default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment: Option<PaymentDetails>,
+}
+
+/// Payment documents history of the agreement.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[non_exhaustive]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentDetails {
+    /// Events of all debit notes, invoices and payments known for the agreement.
+    #[serde(default)]
+    pub events: Vec<DocumentEvent>,
+}
+
+impl From<Vec<DocumentEvent>> for PaymentDetails {
+    fn from(events: Vec<DocumentEvent>) -> Self {
+        PaymentDetails { events }
+    }
+}
+
+/// Kind of payment document.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum DocumentType {
+    DebitNote,
+    Invoice,
+    Payment,
+}
+
+/// Payment document lifecycle event.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum DocumentEventType {
+    /// Document was issued by the provider.
+    Issued,
+    /// Document was accepted by the requestor.
+    Accepted,
+    /// Document was rejected by the requestor.
+    Rejected,
+    /// Document was paid.
+    Settled,
+}
+
+/// Single event in the payment document history.
+///
+/// ```rust
+/// use bigdecimal::BigDecimal;
+/// use chrono::Utc;
+/// use reputation_aggregator_model::*;
+///
+/// let event = DocumentEventBuilder::default()
+///     .document_id("8b2c4b5e-a9a3-4f63-b5f0-05b2d4d0b8a1")
+///     .document_type(DocumentType::Invoice)
+///     .event_type(DocumentEventType::Accepted)
+///     .amount(Some(BigDecimal::from(10)))
+///     .ts(Utc::now())
+///     .build().unwrap();
+///
+/// assert_eq!(event.event_type, DocumentEventType::Accepted)
+/// ```
+#[derive(Builder, Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[builder(setter(into), pattern = "owned")]
+#[non_exhaustive]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentEvent {
+    /// Debit note, invoice or payment id.
+    pub document_id: String,
+    pub document_type: DocumentType,
+    pub event_type: DocumentEventType,
+    /// Amount carried by the document (total amount due for debit notes).
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>))]
+    pub amount: Option<BigDecimal>,
+    /// Event timestamp.
+    pub ts: DateTime<Utc>,
 }
 
 /// Static part of the contract information, unlike the status, has to be reported only once.
 #[derive(Builder, Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[builder(setter(into), pattern = "owned")]
 #[non_exhaustive]
 #[serde(rename_all = "camelCase")]
 pub struct AgreementInfo {
     /// other party to the contract
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     pub peer_id: NodeId,
     /// Contract creation timestamp. since it took effect for node.
     pub created_ts: DateTime<Utc>,
-    ///
+    /// Contract expiration timestamp.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub valid_to: Option<DateTime<Utc>>,
     /// offer `golem.runtime.name`
@@ -81,7 +164,180 @@ pub struct AgreementInfo {
     pub task_package: Option<String>,
 }
 
+/// Side of the agreement.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum AgreementRole {
+    Provider,
+    Requestor,
+}
+
+/// Version of the REST API, selects the url prefix of all endpoints.
+///
+/// Defaults to [`ApiVersion::Legacy`], which every server serves.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ApiVersion {
+    /// Unversioned endpoints mounted at the server root, deprecated.
+    #[default]
+    Legacy,
+    V1,
+}
+
+impl ApiVersion {
+    /// Latest version known to this library.
+    pub const LATEST: ApiVersion = ApiVersion::V1;
+
+    /// Url prefix of endpoints, empty for [`ApiVersion::Legacy`].
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Self::Legacy => "",
+            Self::V1 => "/api/v1",
+        }
+    }
+}
+
+/// API versions served by the aggregator, returned by `GET /api`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ApiVersions {
+    /// Supported versions, unknown ones are kept as strings.
+    pub versions: Vec<String>,
+    /// Versions that still work but will be removed.
+    #[serde(default)]
+    pub deprecated: Vec<String>,
+}
+
+/// Outcome of the agreement derived from reports of both sides.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AgreementClassification {
+    /// Everything that was requested was accepted and paid.
+    Paid,
+    /// Requestor accepted more than was paid.
+    BadRequestor,
+    /// Part of the requested amount was accepted.
+    AgreementBroken,
+    /// Nothing of the requested amount was accepted.
+    AgreementFailed,
+    /// Nothing was requested.
+    Cancelled,
+    #[serde(other)]
+    Unknown,
+}
+
+impl AgreementClassification {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Paid => "PAID",
+            Self::BadRequestor => "BAD_REQUESTOR",
+            Self::AgreementBroken => "AGREEMENT_BROKEN",
+            Self::AgreementFailed => "AGREEMENT_FAILED",
+            Self::Cancelled => "CANCELLED",
+            Self::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+impl From<&str> for AgreementClassification {
+    fn from(s: &str) -> Self {
+        match s {
+            "PAID" => Self::Paid,
+            "BAD_REQUESTOR" => Self::BadRequestor,
+            "AGREEMENT_BROKEN" => Self::AgreementBroken,
+            "AGREEMENT_FAILED" => Self::AgreementFailed,
+            "CANCELLED" | "AGREEMENT_CANCELLED" => Self::Cancelled,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Notification pushed to event stream subscribers.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[non_exhaustive]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ReputationEvent {
+    /// Agreement details were reported by the node.
+    #[serde(rename_all = "camelCase")]
+    AgreementReported {
+        role: AgreementRole,
+        #[cfg_attr(feature = "openapi", schema(value_type = String))]
+        node_id: NodeId,
+        agreement_id: String,
+        agreement: AgreementInfo,
+    },
+    /// Agreement status was reported by the node.
+    #[serde(rename_all = "camelCase")]
+    StatusReported {
+        role: AgreementRole,
+        #[cfg_attr(feature = "openapi", schema(value_type = String))]
+        node_id: NodeId,
+        agreement_id: String,
+        status: Status,
+    },
+    /// Node score changed after scores refresh.
+    #[serde(rename_all = "camelCase")]
+    ScoreChanged {
+        role: AgreementRole,
+        #[cfg_attr(feature = "openapi", schema(value_type = String))]
+        node_id: NodeId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[cfg_attr(feature = "openapi", schema(value_type = Option<String>))]
+        previous: Option<BigDecimal>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[cfg_attr(feature = "openapi", schema(value_type = Option<String>))]
+        score: Option<BigDecimal>,
+    },
+    /// Agreement got a new classification after scores refresh.
+    #[serde(rename_all = "camelCase")]
+    AgreementClassified {
+        role: AgreementRole,
+        #[cfg_attr(feature = "openapi", schema(value_type = String))]
+        node_id: NodeId,
+        agreement_id: String,
+        #[cfg_attr(feature = "openapi", schema(value_type = String))]
+        peer_id: NodeId,
+        classification: AgreementClassification,
+    },
+}
+
+impl ReputationEvent {
+    pub fn role(&self) -> AgreementRole {
+        match self {
+            Self::AgreementReported { role, .. }
+            | Self::StatusReported { role, .. }
+            | Self::ScoreChanged { role, .. }
+            | Self::AgreementClassified { role, .. } => *role,
+        }
+    }
+
+    pub fn node_id(&self) -> NodeId {
+        match self {
+            Self::AgreementReported { node_id, .. }
+            | Self::StatusReported { node_id, .. }
+            | Self::ScoreChanged { node_id, .. }
+            | Self::AgreementClassified { node_id, .. } => *node_id,
+        }
+    }
+
+    /// Event name used in the `event:` field of the SSE stream.
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            Self::AgreementReported { .. } => "agreementReported",
+            Self::StatusReported { .. } => "statusReported",
+            Self::ScoreChanged { .. } => "scoreChanged",
+            Self::AgreementClassified { .. } => "agreementClassified",
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[non_exhaustive]
 pub enum ReportResult {
     #[serde(rename = "ok")]
@@ -97,6 +353,42 @@ impl ReportResult {
     }
 }
 
+/// Stable error code of a failed API call.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    /// Path parameter is malformed, e.g. unknown role or invalid node id (400).
+    InvalidPath,
+    /// Query parameter is malformed (400).
+    InvalidQuery,
+    /// Request body is not valid JSON (400).
+    MalformedBody,
+    /// Request is well-formed but its content is rejected (400).
+    BadRequest,
+    /// Missing or invalid admin token (401).
+    Unauthorized,
+    /// Requested resource does not exist (404).
+    NotFound,
+    /// Resource was already reported (409).
+    Conflict,
+    /// Request body does not match the expected schema (422).
+    InvalidBody,
+    /// Server side failure (500).
+    Internal,
+    #[serde(other)]
+    Unknown,
+}
+
+/// Body of every non-2xx API response.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorResponse {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
 #[cfg(any(feature = "client", feature = "client-old"))]
 mod client;
 
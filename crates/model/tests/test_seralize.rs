@@ -22,3 +22,39 @@ fn test_build() {
         })
     );
 }
+
+#[test]
+fn test_payment_details() {
+    let now = Utc::now();
+    let event = DocumentEventBuilder::default()
+        .document_id("dn-1")
+        .document_type(DocumentType::DebitNote)
+        .event_type(DocumentEventType::Issued)
+        .ts(now)
+        .build()
+        .unwrap();
+    let status = StatusBuilder::default()
+        .requested(10)
+        .ts(now)
+        .payment(Some(PaymentDetails::from(vec![event])))
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        serde_json::to_value(&status).unwrap(),
+        json!({
+            "requested":"10",
+            "accepted":"0",
+            "confirmed":"0",
+            "ts": now,
+            "payment": {
+                "events": [{
+                    "documentId": "dn-1",
+                    "documentType": "debitNote",
+                    "eventType": "issued",
+                    "ts": now
+                }]
+            }
+        })
+    );
+}
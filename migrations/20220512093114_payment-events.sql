@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE agreement_payment_event(
+    role_id  char(1) not null,
+    node_id varchar(42) not null,
+    agreement_id varchar(120) not null,
+    document_id varchar(120) not null,
+    document_type varchar(20) not null,
+    event_type varchar(20) not null,
+    amount decimal,
+    event_ts TIMESTAMPTZ not null,
+    CONSTRAINT agreement_payment_event_pk PRIMARY KEY (role_id, node_id, agreement_id, document_id, event_type)
+);
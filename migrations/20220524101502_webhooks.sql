@@ -0,0 +1,30 @@
+-- Add migration script here
+CREATE TABLE webhook(
+    id bigserial not null,
+    url varchar(500) not null,
+    secret varchar(128) not null,
+    event_types text[] not null default '{}',
+    role_id char(1),
+    node_id varchar(42),
+    threshold decimal,
+    classification varchar(20),
+    created_by varchar(100) not null,
+    created_ts TIMESTAMPTZ not null DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT webhook_pk PRIMARY KEY (id),
+    CONSTRAINT webhook_role_chk CHECK(role_id in ('R', 'P'))
+);
+
+CREATE TABLE webhook_delivery(
+    id bigserial not null,
+    webhook_id bigint not null,
+    event_type varchar(50) not null,
+    payload text not null,
+    attempt int not null,
+    status_code int,
+    error text,
+    delivered_ts TIMESTAMPTZ not null DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT webhook_delivery_pk PRIMARY KEY (id),
+    CONSTRAINT webhook_delivery_webhook_fk FOREIGN KEY (webhook_id) REFERENCES webhook(id) ON DELETE CASCADE
+);
+
+CREATE INDEX webhook_delivery_webhook_idx ON webhook_delivery(webhook_id, delivered_ts);
@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE agreement_flag(
+    agreement_id varchar(120) not null,
+    weight decimal not null,
+    reason varchar(30) not null,
+    flagged_ts TIMESTAMPTZ not null DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT agreement_flag_pk PRIMARY KEY (agreement_id),
+    CONSTRAINT agreement_flag_weight_chk CHECK(weight >= 0 AND weight <= 1)
+);
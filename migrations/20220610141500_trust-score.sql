@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE trust_score(
+    role_id char(1) not null,
+    node_id varchar(42) not null,
+    trust double precision not null,
+    raw_score decimal not null,
+    standard_score decimal,
+    computed_ts TIMESTAMPTZ not null DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT trust_score_pk PRIMARY KEY (role_id, node_id),
+    CONSTRAINT trust_score_role_chk CHECK(role_id in ('R', 'P'))
+);
@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE node_archive(
+    role_id char(1) not null,
+    node_id varchar(42) not null,
+    agreements bigint not null,
+    raw_score decimal not null,
+    requested decimal not null,
+    accepted decimal not null,
+    confirmed decimal not null,
+    first_seen timestamp without time zone not null,
+    last_seen timestamp without time zone not null,
+    CONSTRAINT node_archive_pk PRIMARY KEY (role_id, node_id),
+    CONSTRAINT node_archive_role_chk CHECK(role_id in ('R', 'P'))
+);
@@ -0,0 +1,50 @@
+-- Add migration script here
+CREATE TABLE agreement_quarantine(
+    agreement_id varchar(120) not null,
+    reason varchar(500),
+    created_by varchar(100) not null,
+    created_ts TIMESTAMPTZ not null DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT agreement_quarantine_pk PRIMARY KEY (agreement_id)
+);
+
+CREATE TABLE node_moderation(
+    node_id varchar(42) not null,
+    status varchar(20) not null,
+    reason varchar(500),
+    created_by varchar(100) not null,
+    created_ts TIMESTAMPTZ not null DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT node_moderation_pk PRIMARY KEY (node_id),
+    CONSTRAINT node_moderation_status_chk CHECK(status in ('banned', 'whitelisted'))
+);
+
+CREATE TABLE node_note(
+    id bigserial not null,
+    node_id varchar(42) not null,
+    note text not null,
+    created_by varchar(100) not null,
+    created_ts TIMESTAMPTZ not null DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT node_note_pk PRIMARY KEY (id)
+);
+
+CREATE INDEX node_note_node_idx ON node_note(node_id);
+
+CREATE TABLE score_override(
+    role_id char(1) not null,
+    node_id varchar(42) not null,
+    standard_score decimal not null,
+    reason varchar(500) not null,
+    created_by varchar(100) not null,
+    created_ts TIMESTAMPTZ not null DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT score_override_pk PRIMARY KEY (role_id, node_id),
+    CONSTRAINT score_override_role_chk CHECK(role_id in ('R', 'P'))
+);
+
+CREATE TABLE moderation_audit(
+    id bigserial not null,
+    principal varchar(100) not null,
+    action varchar(50) not null,
+    target varchar(200) not null,
+    details jsonb not null,
+    created_ts TIMESTAMPTZ not null DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT moderation_audit_pk PRIMARY KEY (id)
+);
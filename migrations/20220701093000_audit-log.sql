@@ -0,0 +1,31 @@
+-- Add migration script here
+CREATE TABLE audit_log(
+    id bigserial not null,
+    operation varchar(50) not null,
+    target varchar(200) not null,
+    principal varchar(100),
+    source_ip varchar(45),
+    request_id varchar(64),
+    payload_sha256 varchar(64),
+    created_ts TIMESTAMPTZ not null DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT audit_log_pk PRIMARY KEY (id)
+);
+
+CREATE INDEX audit_log_target_idx ON audit_log(target);
+
+-- Audit log is append-only.
+CREATE FUNCTION audit_log_append_only() RETURNS trigger
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$;
+
+CREATE TRIGGER audit_log_no_update
+    BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION audit_log_append_only();
+
+CREATE TRIGGER audit_log_no_truncate
+    BEFORE TRUNCATE ON audit_log
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_log_append_only();
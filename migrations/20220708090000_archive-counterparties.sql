@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Distinct counterparties of archived agreements, empty for agreements archived before.
+ALTER TABLE node_archive ADD COLUMN counterparties varchar(42)[] not null default '{}';
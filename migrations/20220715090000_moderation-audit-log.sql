@@ -0,0 +1,19 @@
+-- Add migration script here
+-- Moderation actions are served from the audit log. Actions taken before it existed are
+-- copied over, later ones were recorded in both tables within the same transaction.
+INSERT INTO audit_log(operation, target, principal, created_ts)
+SELECT m.action, m.target, m.principal, m.created_ts
+  FROM moderation_audit m
+ WHERE NOT EXISTS (
+        SELECT 1
+          FROM audit_log a
+         WHERE a.operation = m.action
+           AND a.target = m.target
+           AND a.principal = m.principal
+           AND a.created_ts BETWEEN m.created_ts - interval '1 second'
+                                AND m.created_ts + interval '1 second')
+ ORDER BY m.id;
+
+DROP TABLE moderation_audit;
+
+CREATE INDEX audit_log_operation_idx ON audit_log(operation);
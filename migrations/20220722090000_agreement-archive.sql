@@ -0,0 +1,20 @@
+-- Add migration script here
+-- Scores of agreements removed by retention, summed per provider, requestor, payment platform
+-- and subnet, so that segmented and trust scores keep them as well. node_archive holds the
+-- per-node aggregates of agreements removed before.
+CREATE TABLE agreement_archive(
+    p_id varchar(42) not null,
+    r_id varchar(42) not null,
+    -- empty when not reported
+    payment_platform varchar(50) not null,
+    subnet varchar(120) not null,
+    agreements bigint not null,
+    p_score decimal not null,
+    r_score decimal not null,
+    -- sums of squared scores, for the spread of agreement scores
+    p_score_sq decimal not null,
+    r_score_sq decimal not null,
+    CONSTRAINT agreement_archive_pk PRIMARY KEY (p_id, r_id, payment_platform, subnet)
+);
+
+CREATE INDEX agreement_archive_r_id_idx ON agreement_archive(r_id);
@@ -0,0 +1,474 @@
+//! Sybil and collusion detection over the provider-requestor agreement graph.
+//!
+//! Every agreement is an edge between the provider and the requestor. Three patterns are
+//! flagged, and positive scores of the agreements behind them are down-weighted when scores
+//! are calculated, penalties count in full:
+//!
+//! * tight clusters - groups of nodes that trade almost exclusively with each other,
+//! * reciprocal-only nodes - nodes whose every counterparty is also serving them back,
+//! * shared payment addresses - several provider ids receiving payments on one address.
+
+use crate::dao::AgreementEdge;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Minimum number of agreements on an edge for it to be considered a cluster link.
+const CLUSTER_MIN_AGREEMENTS: usize = 5;
+/// Share of both endpoints' agreements an edge must hold to be a cluster link.
+const CLUSTER_MIN_SHARE: f64 = 0.8;
+const CLUSTER_WEIGHT: f64 = 0.1;
+const RECIPROCAL_WEIGHT: f64 = 0.5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum FindingKind {
+    TightCluster,
+    ReciprocalOnly,
+    SharedPaymentAddress,
+}
+
+impl FindingKind {
+    pub fn as_db(&self) -> &'static str {
+        match self {
+            FindingKind::TightCluster => "tight_cluster",
+            FindingKind::ReciprocalOnly => "reciprocal_only",
+            FindingKind::SharedPaymentAddress => "shared_payment_address",
+        }
+    }
+}
+
+/// Group of nodes matching one of the suspicious patterns.
+#[derive(Serialize, Debug, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub node_ids: Vec<String>,
+    /// Number of agreements down-weighted because of this finding.
+    pub agreements: usize,
+    /// Weight applied to scores of these agreements.
+    pub weight: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_address: Option<String>,
+}
+
+/// Weight assigned to an agreement, the lowest one wins when several findings overlap.
+#[derive(Debug, Clone, Copy)]
+pub struct AgreementFlag {
+    pub weight: f64,
+    pub kind: FindingKind,
+}
+
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub findings: Vec<Finding>,
+    pub flags: BTreeMap<String, AgreementFlag>,
+}
+
+impl Analysis {
+    fn flag<'a>(
+        &mut self,
+        kind: FindingKind,
+        node_ids: Vec<String>,
+        weight: f64,
+        payment_address: Option<String>,
+        agreement_ids: impl Iterator<Item = &'a str>,
+    ) {
+        let mut agreements = 0;
+        for agreement_id in agreement_ids {
+            agreements += 1;
+            let flag = self
+                .flags
+                .entry(agreement_id.to_string())
+                .or_insert(AgreementFlag { weight, kind });
+            if weight < flag.weight {
+                *flag = AgreementFlag { weight, kind };
+            }
+        }
+        self.findings.push(Finding {
+            kind,
+            node_ids,
+            agreements,
+            weight,
+            payment_address,
+        });
+    }
+}
+
+/// Disjoint sets over node ids.
+#[derive(Default)]
+struct Components<'a> {
+    parent: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Components<'a> {
+    fn find(&mut self, node: &'a str) -> &'a str {
+        let parent = *self.parent.entry(node).or_insert(node);
+        if parent == node {
+            return node;
+        }
+        let root = self.find(parent);
+        self.parent.insert(node, root);
+        root
+    }
+
+    fn union(&mut self, a: &'a str, b: &'a str) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent.insert(a, b);
+        }
+    }
+}
+
+pub fn analyze(edges: &[AgreementEdge]) -> Analysis {
+    let mut analysis = Analysis::default();
+    tight_clusters(edges, &mut analysis);
+    reciprocal_only(edges, &mut analysis);
+    shared_payment_addresses(edges, &mut analysis);
+    analysis
+}
+
+fn tight_clusters(edges: &[AgreementEdge], analysis: &mut Analysis) {
+    let mut by_pair: HashMap<(&str, &str), Vec<&str>> = HashMap::new();
+    let mut provided: HashMap<&str, usize> = HashMap::new();
+    let mut requested: HashMap<&str, usize> = HashMap::new();
+    for edge in edges {
+        by_pair
+            .entry((edge.provider_id.as_str(), edge.requestor_id.as_str()))
+            .or_default()
+            .push(&edge.agreement_id);
+        *provided.entry(&edge.provider_id).or_default() += 1;
+        *requested.entry(&edge.requestor_id).or_default() += 1;
+    }
+
+    let mut components = Components::default();
+    let mut links = Vec::new();
+    for (&(provider_id, requestor_id), agreement_ids) in &by_pair {
+        let count = agreement_ids.len();
+        if count >= CLUSTER_MIN_AGREEMENTS
+            && count as f64 >= CLUSTER_MIN_SHARE * provided[provider_id] as f64
+            && count as f64 >= CLUSTER_MIN_SHARE * requested[requestor_id] as f64
+        {
+            components.union(provider_id, requestor_id);
+            links.push((provider_id, agreement_ids));
+        }
+    }
+
+    let mut clusters: BTreeMap<&str, (BTreeSet<&str>, Vec<&str>)> = BTreeMap::new();
+    for (provider_id, agreement_ids) in links {
+        let root = components.find(provider_id);
+        clusters.entry(root).or_default().1.extend(agreement_ids);
+    }
+    let nodes: Vec<&str> = components.parent.keys().copied().collect();
+    for node in nodes {
+        let root = components.find(node);
+        if let Some((members, _)) = clusters.get_mut(root) {
+            members.insert(node);
+        }
+    }
+    for (members, agreement_ids) in clusters.into_values() {
+        analysis.flag(
+            FindingKind::TightCluster,
+            members.into_iter().map(str::to_string).collect(),
+            CLUSTER_WEIGHT,
+            None,
+            agreement_ids.into_iter(),
+        );
+    }
+}
+
+fn reciprocal_only(edges: &[AgreementEdge], analysis: &mut Analysis) {
+    let mut served: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    let mut served_by: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    for edge in edges {
+        served
+            .entry(&edge.provider_id)
+            .or_default()
+            .insert(&edge.requestor_id);
+        served_by
+            .entry(&edge.requestor_id)
+            .or_default()
+            .insert(&edge.provider_id);
+    }
+
+    let mut groups: BTreeSet<BTreeSet<&str>> = BTreeSet::new();
+    for (&node, requestors) in &served {
+        let providers = match served_by.get(node) {
+            Some(providers) => providers,
+            None => continue,
+        };
+        if requestors == providers && !requestors.contains(node) {
+            let mut group = requestors.clone();
+            group.insert(node);
+            groups.insert(group);
+        }
+    }
+
+    for group in groups {
+        let agreement_ids = edges
+            .iter()
+            .filter(|edge| {
+                group.contains(edge.provider_id.as_str())
+                    && group.contains(edge.requestor_id.as_str())
+            })
+            .map(|edge| edge.agreement_id.as_str());
+        analysis.flag(
+            FindingKind::ReciprocalOnly,
+            group.iter().map(|node| node.to_string()).collect(),
+            RECIPROCAL_WEIGHT,
+            None,
+            agreement_ids,
+        );
+    }
+}
+
+fn shared_payment_addresses(edges: &[AgreementEdge], analysis: &mut Analysis) {
+    let mut providers: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for edge in edges {
+        if let Some(address) = &edge.payment_address {
+            providers
+                .entry(address)
+                .or_default()
+                .insert(&edge.provider_id);
+        }
+    }
+
+    for (address, node_ids) in providers {
+        if node_ids.len() < 2 {
+            continue;
+        }
+        // All identities behind one address together count as a single provider.
+        let weight = 1.0 / node_ids.len() as f64;
+        let agreement_ids = edges
+            .iter()
+            .filter(|edge| edge.payment_address.as_deref() == Some(address))
+            .map(|edge| edge.agreement_id.as_str());
+        analysis.flag(
+            FindingKind::SharedPaymentAddress,
+            node_ids.iter().map(|node| node.to_string()).collect(),
+            weight,
+            Some(address.to_string()),
+            agreement_ids,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use sqlx::Executor;
+
+    fn edge(agreement_id: &str, provider_id: &str, requestor_id: &str) -> AgreementEdge {
+        AgreementEdge {
+            agreement_id: agreement_id.to_string(),
+            provider_id: provider_id.to_string(),
+            requestor_id: requestor_id.to_string(),
+            payment_address: None,
+        }
+    }
+
+    fn paid(mut edge: AgreementEdge, address: &str) -> AgreementEdge {
+        edge.payment_address = Some(address.to_string());
+        edge
+    }
+
+    fn flagged(analysis: &Analysis) -> Vec<(&str, FindingKind, f64)> {
+        analysis
+            .flags
+            .iter()
+            .map(|(id, flag)| (id.as_str(), flag.kind, flag.weight))
+            .collect()
+    }
+
+    #[test]
+    fn tight_cluster() {
+        let mut edges: Vec<_> = (0..5).map(|i| edge(&format!("a{i}"), "p1", "r1")).collect();
+        edges.push(edge("b", "p2", "r2"));
+
+        let mut analysis = Analysis::default();
+        tight_clusters(&edges, &mut analysis);
+
+        assert_eq!(analysis.findings.len(), 1);
+        assert_eq!(analysis.findings[0].kind, FindingKind::TightCluster);
+        assert_eq!(analysis.findings[0].node_ids, ["p1", "r1"]);
+        assert_eq!(analysis.findings[0].agreements, 5);
+        assert_eq!(analysis.flags.len(), 5);
+        assert!(!analysis.flags.contains_key("b"));
+    }
+
+    #[test]
+    fn reciprocal() {
+        let edges = [
+            edge("a", "n1", "n2"),
+            edge("b", "n2", "n1"),
+            edge("c", "n3", "n1"),
+        ];
+
+        let mut analysis = Analysis::default();
+        reciprocal_only(&edges, &mut analysis);
+
+        assert_eq!(analysis.findings.len(), 1);
+        assert_eq!(analysis.findings[0].node_ids, ["n1", "n2"]);
+        assert_eq!(
+            flagged(&analysis),
+            [
+                ("a", FindingKind::ReciprocalOnly, RECIPROCAL_WEIGHT),
+                ("b", FindingKind::ReciprocalOnly, RECIPROCAL_WEIGHT),
+            ]
+        );
+    }
+
+    #[test]
+    fn shared_payment_address() {
+        let edges = [
+            paid(edge("a", "p1", "r1"), "0xa"),
+            paid(edge("b", "p2", "r2"), "0xa"),
+            paid(edge("c", "p2", "r3"), "0xb"),
+            edge("d", "p1", "r4"),
+        ];
+
+        let mut analysis = Analysis::default();
+        shared_payment_addresses(&edges, &mut analysis);
+
+        assert_eq!(analysis.findings.len(), 1);
+        assert_eq!(analysis.findings[0].node_ids, ["p1", "p2"]);
+        assert_eq!(analysis.findings[0].payment_address.as_deref(), Some("0xa"));
+        // Agreements paid elsewhere are not down-weighted.
+        assert_eq!(
+            flagged(&analysis),
+            [
+                ("a", FindingKind::SharedPaymentAddress, 0.5),
+                ("b", FindingKind::SharedPaymentAddress, 0.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_findings_take_lowest_weight() {
+        let mut edges: Vec<_> = (0..5).map(|i| edge(&format!("a{i}"), "n1", "n2")).collect();
+        edges.push(edge("b", "n2", "n1"));
+
+        let analysis = analyze(&edges);
+
+        assert_eq!(analysis.findings.len(), 2);
+        for i in 0..5 {
+            let flag = analysis.flags[&format!("a{i}")];
+            assert_eq!(flag.kind, FindingKind::TightCluster);
+            assert_eq!(flag.weight, CLUSTER_WEIGHT);
+        }
+        let flag = analysis.flags["b"];
+        assert_eq!(flag.kind, FindingKind::ReciprocalOnly);
+        assert_eq!(flag.weight, RECIPROCAL_WEIGHT);
+    }
+
+    #[test]
+    fn ordinary_market_is_not_flagged() {
+        let edges = [
+            paid(edge("a", "p1", "r1"), "0xa"),
+            paid(edge("b", "p1", "r2"), "0xa"),
+            paid(edge("c", "p2", "r1"), "0xb"),
+            edge("d", "p2", "r2"),
+            edge("e", "p3", "r1"),
+            edge("f", "p3", "r1"),
+        ];
+
+        let analysis = analyze(&edges);
+
+        assert!(analysis.findings.is_empty());
+        assert!(analysis.flags.is_empty());
+    }
+
+    /// Flag weights are applied by `calc.agreement`, this runs `calc.sql` in a transaction
+    /// that is rolled back.
+    #[actix_rt::test]
+    #[ignore = "needs DATABASE_URL of a migrated database"]
+    async fn flag_weight_spares_negative_scores() {
+        let pool = sqlx::PgPool::connect(&std::env::var("DATABASE_URL").unwrap())
+            .await
+            .unwrap();
+        let mut tx = pool.begin().await.unwrap();
+        tx.execute(crate::dao::SCORING_SQL).await.unwrap();
+        tx.execute("RESET search_path").await.unwrap();
+        for (agreement_id, accepted, confirmed) in [
+            ("analysis-test-bad-requestor", 10, 5),
+            ("analysis-test-paid", 10, 10),
+        ] {
+            sqlx::query!(
+                r#"
+                INSERT INTO AGREEMENT_DETAILS(role_id, node_id, agreement_id, peer_id, created_ts)
+                VALUES('P', '0xp', $1, '0xr', CURRENT_TIMESTAMP)"#,
+                agreement_id
+            )
+            .execute(&mut tx)
+            .await
+            .unwrap();
+            sqlx::query!(
+                r#"
+                INSERT INTO AGREEMENT_STATUS(role_id, node_id, agreement_id, peer_id, requested,
+                    accepted, confirmed)
+                VALUES('P', '0xp', $1, '0xr', 10, $2, $3)"#,
+                agreement_id,
+                BigDecimal::from(accepted),
+                BigDecimal::from(confirmed)
+            )
+            .execute(&mut tx)
+            .await
+            .unwrap();
+            sqlx::query!(
+                r#"
+                INSERT INTO AGREEMENT_FLAG(agreement_id, weight, reason)
+                VALUES($1, $2::float8, $3)"#,
+                agreement_id,
+                CLUSTER_WEIGHT,
+                FindingKind::TightCluster.as_db()
+            )
+            .execute(&mut tx)
+            .await
+            .unwrap();
+        }
+        tx.execute("REFRESH MATERIALIZED VIEW calc.agreement")
+            .await
+            .unwrap();
+
+        let scores: Vec<_> = sqlx::query!(
+            r#"
+            SELECT agreement_id AS "agreement_id!", agreement_result AS "agreement_result!",
+                p_score::float8 AS "p_score!", r_score::float8 AS "r_score!"
+            FROM calc.agreement
+            WHERE agreement_id LIKE 'analysis-test-%'
+            ORDER BY agreement_id"#
+        )
+        .fetch_all(&mut tx)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| {
+            (
+                row.agreement_id,
+                row.agreement_result,
+                row.p_score,
+                row.r_score,
+            )
+        })
+        .collect();
+
+        // Accepted but unpaid amount costs the requestor in full, flagged or not.
+        assert_eq!(
+            scores,
+            [
+                (
+                    "analysis-test-bad-requestor".to_string(),
+                    "BAD_REQUESTOR".to_string(),
+                    0.0,
+                    -50.0
+                ),
+                (
+                    "analysis-test-paid".to_string(),
+                    "PAID".to_string(),
+                    1.0,
+                    1.0
+                ),
+            ]
+        );
+    }
+}
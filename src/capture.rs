@@ -0,0 +1,67 @@
+//! JSONL capture format of agreement and status reports.
+//!
+//! Every line holds one [`CaptureRecord`]:
+//! ```json
+//! {"ts":"2022-05-10T12:00:00Z","role":"provider","nodeId":"0x...","agreementId":"...","kind":"status","body":{...}}
+//! ```
+use chrono::{DateTime, Duration, Utc};
+use reputation_aggregator_model::{AgreementInfo, AgreementRole, NodeId, Status};
+use serde::{Deserialize, Serialize};
+use std::io::BufRead;
+
+/// Request body captured for an agreement.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", content = "body", rename_all = "camelCase")]
+pub enum CapturedRequest {
+    /// `POST /{role_id}/{node_id}/agreement/{agreement_id}`
+    Agreement(AgreementInfo),
+    /// `POST /{role_id}/{node_id}/agreement/{agreement_id}/status`
+    Status(Status),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureRecord {
+    /// Time the request was received.
+    pub ts: DateTime<Utc>,
+    pub role: AgreementRole,
+    pub node_id: NodeId,
+    pub agreement_id: String,
+    #[serde(flatten)]
+    pub request: CapturedRequest,
+}
+
+impl CaptureRecord {
+    /// Moves all timestamps carried by the record (including the request body) by `offset`.
+    pub fn shift_timestamps(&mut self, offset: Duration) {
+        self.ts += offset;
+        match &mut self.request {
+            CapturedRequest::Agreement(info) => {
+                info.created_ts += offset;
+                info.valid_to = info.valid_to.map(|ts| ts + offset);
+            }
+            CapturedRequest::Status(status) => {
+                status.ts += offset;
+                status.payment_due_ts = status.payment_due_ts.map(|ts| ts + offset);
+                for event in status.payment.iter_mut().flat_map(|p| p.events.iter_mut()) {
+                    event.ts += offset;
+                }
+            }
+        }
+    }
+}
+
+/// Reads all records from a JSONL capture, skipping empty lines.
+pub fn read_records(reader: impl BufRead) -> anyhow::Result<Vec<CaptureRecord>> {
+    let mut records = Vec::new();
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line)
+            .map_err(|e| anyhow::anyhow!("invalid record at line {}: {}", line_no + 1, e))?;
+        records.push(record);
+    }
+    Ok(records)
+}
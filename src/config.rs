@@ -1,22 +1,243 @@
+use crate::dao::MinEvidence;
 use ::config::{Config, Environment, File};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
 #[derive(Deserialize, Debug)]
-pub(crate) struct ReputationServerConfig {
+pub struct ReputationServerConfig {
     pub listen_on: SocketAddr,
     pub apply_migrations: bool,
     pub database_url: String,
+    /// Seconds between score recalculations, no periodic refresh when not set.
+    pub score_refresh_interval: Option<u64>,
+    /// Admin API bearer tokens by principal name, admin API is disabled when empty.
+    #[serde(default)]
+    pub admin_tokens: HashMap<String, String>,
+    /// Scores based on fewer agreements are reported as insufficient data, none by default.
+    /// Changed thresholds apply from the next score refresh.
+    #[serde(default)]
+    pub min_evidence_agreements: i64,
+    /// Scores based on fewer distinct counterparties are reported as insufficient data.
+    /// Like agreements, counterparties of agreements folded by retention count as well.
+    #[serde(default)]
+    pub min_evidence_counterparties: i64,
+    /// Raw reports are kept forever when not set.
+    #[serde(default)]
+    pub retention: Option<RetentionConfig>,
+    /// Listing and score queries of the API go to this replica while it keeps up.
+    #[serde(default)]
+    pub read_replica: Option<ReadReplicaConfig>,
+    #[serde(default)]
+    pub score_cache: ScoreCacheConfig,
+    /// Database connection pool shared by all workers and background tasks.
+    #[serde(default)]
+    pub pool: PoolConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// Seconds in-flight requests are given to finish on SIGTERM, SIGINT stops immediately.
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+    /// HTTPS on `listen_on` when set, plain HTTP otherwise.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub cors: CorsConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf certificate first.
+    pub cert_path: PathBuf,
+    /// PEM private key, PKCS#8, RSA or SEC1.
+    pub key_path: PathBuf,
+    /// Seconds between checks for a renewed certificate, new connections use it once loaded.
+    /// Zero disables reloading.
+    #[serde(default = "default_tls_reload_interval")]
+    pub reload_interval: u64,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API from a browser, e.g. `https://stats.golem.network`.
+    /// `*` allows any origin. Cross-origin requests are not answered when empty.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Seconds browsers may cache a preflight response.
+    #[serde(default)]
+    pub max_age: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PoolConfig {
+    #[serde(default = "default_max_connections")]
+    pub max_connections: u32,
+    #[serde(default)]
+    pub min_connections: u32,
+    /// Seconds to wait for a free connection before the query fails.
+    #[serde(default = "default_acquire_timeout")]
+    pub acquire_timeout: u64,
+    /// Seconds an unused connection above `min_connections` is kept open.
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: Option<u64>,
+    /// Seconds after which a connection is replaced.
+    #[serde(default = "default_max_lifetime")]
+    pub max_lifetime: Option<u64>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_connections: default_max_connections(),
+            min_connections: 0,
+            acquire_timeout: default_acquire_timeout(),
+            idle_timeout: default_idle_timeout(),
+            max_lifetime: default_max_lifetime(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ReadReplicaConfig {
+    /// Pool settings are the same as for the primary.
+    pub database_url: String,
+    /// Seconds the replica may be behind the primary before reads fall back to the primary.
+    #[serde(default = "default_replica_max_lag")]
+    pub max_lag: u64,
+    /// Seconds between replica lag checks.
+    #[serde(default = "default_replica_check_interval")]
+    pub check_interval: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScoreCacheConfig {
+    /// Score lookups kept in memory, zero disables the cache.
+    #[serde(default = "default_score_cache_entries")]
+    pub max_entries: usize,
+    /// Seconds a score is served from the cache, and may be cached by clients, when it is
+    /// changed by another process, e.g. `refresh-scores`.
+    #[serde(default = "default_score_cache_max_age")]
+    pub max_age: u64,
+}
+
+impl Default for ScoreCacheConfig {
+    fn default() -> Self {
+        ScoreCacheConfig {
+            max_entries: default_score_cache_entries(),
+            max_age: default_score_cache_max_age(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LoggingConfig {
+    /// Filter directives, e.g. `info,sqlx=warn`. `RUST_LOG` takes precedence when set.
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    #[serde(default = "default_log_format")]
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: default_log_level(),
+            format: default_log_format(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RetentionConfig {
+    /// Agreements without any update for this many days are folded into per-node aggregates
+    /// and their raw reports removed.
+    pub max_age_days: u32,
+    /// Directory receiving a JSONL archive of removed reports.
+    pub archive_dir: PathBuf,
+    /// Seconds between retention runs.
+    #[serde(default = "default_retention_interval")]
+    pub interval: u64,
+}
+
+fn default_retention_interval() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_tls_reload_interval() -> u64 {
+    5 * 60
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
+fn default_max_connections() -> u32 {
+    10
+}
+
+fn default_acquire_timeout() -> u64 {
+    30
+}
+
+fn default_idle_timeout() -> Option<u64> {
+    Some(10 * 60)
+}
+
+fn default_max_lifetime() -> Option<u64> {
+    Some(30 * 60)
+}
+
+fn default_replica_max_lag() -> u64 {
+    30
+}
+
+fn default_replica_check_interval() -> u64 {
+    10
+}
+
+fn default_score_cache_entries() -> usize {
+    10_000
+}
+
+fn default_score_cache_max_age() -> u64 {
+    60
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_log_format() -> LogFormat {
+    LogFormat::Text
 }
 
 impl ReputationServerConfig {
+    /// Reads `repu-config.*` from the working directory, overridden by `REPU__` environment
+    /// variables with `__` between nested keys, e.g. `REPU__DATABASE_URL` or
+    /// `REPU__POOL__MAX_CONNECTIONS`.
     pub fn load() -> anyhow::Result<Self> {
         Ok(Config::builder()
             .set_default("listen_on", "127.0.0.1:8080")?
             .set_default("apply_migrations", true)?
-            .add_source(Environment::with_prefix("repu"))
+            .add_source(Environment::with_prefix("repu").separator("__"))
             .add_source(File::with_name("repu-config").required(false))
             .build()?
             .try_deserialize::<Self>()?)
     }
+
+    pub fn min_evidence(&self) -> MinEvidence {
+        MinEvidence {
+            agreements: self.min_evidence_agreements,
+            counterparties: self.min_evidence_counterparties,
+        }
+    }
 }
@@ -1,39 +1,748 @@
+use crate::analysis::AgreementFlag;
+use crate::capture::{CaptureRecord, CapturedRequest};
+use crate::config::{PoolConfig, ReadReplicaConfig, ScoreCacheConfig};
+use crate::score_cache::{ScoreCache, ScoreKey};
+use crate::trust::TrustScore;
+use bigdecimal::ToPrimitive;
+use futures::stream::BoxStream;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sqlx::migrate::Migrator;
-use sqlx::types::chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::types::chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
 use sqlx::types::BigDecimal;
-use sqlx::{PgPool, Pool, Postgres};
+use sqlx::{Executor, PgPool, Pool, Postgres, Transaction};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use reputation_aggregator_model::{
-    AgreementInfo, AgreementInfoBuilder, NodeId, Status, StatusBuilder,
+    AgreementClassification, AgreementInfo, AgreementInfoBuilder, AgreementRole, DocumentEventType,
+    DocumentType, NodeId, Status, StatusBuilder,
 };
 
 static MIGRATOR: Migrator = sqlx::migrate!();
 
+/// How long the delivery worker uses loaded webhooks without checking for changes.
+const WEBHOOK_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Webhooks with the time they were loaded.
+type LoadedWebhooks = (Instant, Arc<Vec<Webhook>>);
+
+/// Scoring schema, see the notes at the top of the script.
+pub(crate) const SCORING_SQL: &str = include_str!("../calc.sql");
+
+#[derive(Clone)]
 pub struct StatusDao {
     pool: PgPool,
+    replica: Option<Replica>,
+    score_cache: ScoreCache,
+    /// Thresholds ranks are computed with, see [`StatusDao::refresh_ranking`].
+    min_evidence: MinEvidence,
+    /// Webhooks as seen by the delivery worker, dropped when one is added or removed.
+    webhooks: Arc<tokio::sync::Mutex<Option<LoadedWebhooks>>>,
+}
+
+/// Read replica taking listing and score queries off the primary while it keeps up.
+#[derive(Clone)]
+struct Replica {
+    pool: PgPool,
+    /// Set by the replica monitor, reads go to the primary until the first successful check.
+    healthy: Arc<AtomicBool>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Agreement {
     agreement_id: String,
     peer_id: String,
     created_ts: DateTime<Utc>,
     status: Status,
+    /// Outcome of the agreement, `UNKNOWN` until scores are refreshed.
+    classification: AgreementClassification,
+    /// Contribution of the agreement to the raw score of the reporting node.
+    #[schema(value_type = Option<String>)]
+    score_contribution: Option<BigDecimal>,
+}
+
+impl Agreement {
+    pub fn classification(&self) -> AgreementClassification {
+        self.classification
+    }
+}
+
+/// Minimum amount of evidence a score has to be based on to be reported.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinEvidence {
+    pub agreements: i64,
+    pub counterparties: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ScoreStatus {
+    Ok,
+    /// Score is based on fewer agreements or counterparties than required.
+    InsufficientData,
+    /// Node has no score yet.
+    NotScored,
+    /// Node was banned by an operator.
+    Banned,
+}
+
+/// Approximate 95% confidence interval of the standard score.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, utoipa::ToSchema)]
+pub struct ConfidenceInterval {
+    pub lower: f64,
+    pub upper: f64,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, utoipa::ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct StandardScore {
+    /// Set only with `ok` status.
+    #[schema(value_type = Option<String>)]
     pub score: Option<BigDecimal>,
+    pub status: ScoreStatus,
+    /// 1-based position among nodes of the role with sufficient evidence, best score first.
+    pub rank: Option<i64>,
+    /// Percentage of nodes of the role with sufficient evidence and a lower score.
+    pub percentile: Option<f64>,
+    /// Number of scored agreements.
+    pub agreements: i64,
+    /// Number of distinct counterparties of scored agreements.
+    pub counterparties: i64,
+    pub confidence_interval: Option<ConfidenceInterval>,
+    /// Score was set by an operator, see `/admin/override`.
+    pub overridden: bool,
+}
+
+impl StandardScore {
+    fn not_scored() -> Self {
+        StandardScore {
+            score: None,
+            status: ScoreStatus::NotScored,
+            rank: None,
+            percentile: None,
+            agreements: 0,
+            counterparties: 0,
+            confidence_interval: None,
+            overridden: false,
+        }
+    }
+}
+
+/// Leaderboard entry.
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RankedNode {
+    pub node_id: String,
+    #[serde(flatten)]
+    pub score: StandardScore,
+}
+
+#[derive(sqlx::FromRow)]
+struct ScoreRow {
+    node_id: String,
+    score: Option<BigDecimal>,
+    agreements: i64,
+    counterparties: i64,
+    margin: Option<f64>,
+    sufficient: bool,
+    banned: bool,
+    overridden: bool,
+    rank: Option<i64>,
+    percentile: Option<f64>,
+}
+
+impl From<ScoreRow> for StandardScore {
+    fn from(row: ScoreRow) -> Self {
+        let status = match (&row.score, row.sufficient) {
+            _ if row.banned => ScoreStatus::Banned,
+            (None, _) => ScoreStatus::NotScored,
+            (Some(_), false) => ScoreStatus::InsufficientData,
+            (Some(_), true) => ScoreStatus::Ok,
+        };
+        if status != ScoreStatus::Ok {
+            return StandardScore {
+                status,
+                agreements: row.agreements,
+                counterparties: row.counterparties,
+                ..StandardScore::not_scored()
+            };
+        }
+        let confidence_interval = row
+            .score
+            .as_ref()
+            .and_then(ToPrimitive::to_f64)
+            .zip(row.margin)
+            .map(|(score, margin)| ConfidenceInterval {
+                lower: score - margin,
+                upper: score + margin,
+            });
+        StandardScore {
+            score: row.score,
+            status,
+            rank: row.rank,
+            percentile: row.percentile,
+            agreements: row.agreements,
+            counterparties: row.counterparties,
+            confidence_interval,
+            overridden: row.overridden,
+        }
+    }
+}
+
+/// Mainnet or testnet, derived from the payment platform name.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Mainnet,
+    Testnet,
+}
+
+/// How agreements are weighted when summing up a score.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ScoringAlgorithm {
+    /// Every agreement counts the same.
+    #[default]
+    Standard,
+    /// Agreements count in proportion to the trust of the counterparty, see [`crate::trust`].
+    Trust,
+}
+
+/// Subset of agreements a score is computed from.
+#[derive(Debug, Clone)]
+pub enum Segment {
+    PaymentPlatform(String),
+    Network(Network),
+    Subnet(String),
+}
+
+impl Segment {
+    /// `(segment_type, segment)` as stored in `calc.segment_score`.
+    fn as_db(&self) -> (&'static str, &str) {
+        match self {
+            Segment::PaymentPlatform(platform) => ("payment_platform", platform),
+            Segment::Network(Network::Mainnet) => ("network", "mainnet"),
+            Segment::Network(Network::Testnet) => ("network", "testnet"),
+            Segment::Subnet(subnet) => ("subnet", subnet),
+        }
+    }
+
+    /// `(segment_type, segment)` as stored in `calc.ranked_score`, segments only apply to the
+    /// standard algorithm.
+    fn bind_values(algorithm: ScoringAlgorithm, segment: Option<&Segment>) -> (&'static str, &str) {
+        match (algorithm, segment.map(Segment::as_db)) {
+            (ScoringAlgorithm::Trust, _) => ("trust", ""),
+            (ScoringAlgorithm::Standard, Some(db)) => db,
+            (ScoringAlgorithm::Standard, None) => ("", ""),
+        }
+    }
+}
+
+/// Everything known about a node in one of its roles.
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeProfile {
+    pub node_id: String,
+    pub role: AgreementRole,
+    /// Number of agreements with reported status.
+    pub agreements: i64,
+    /// Agreement counts by classification, unclassified ones are counted as `UNKNOWN`.
+    pub classifications: BTreeMap<String, i64>,
+    #[schema(value_type = String)]
+    pub requested: BigDecimal,
+    #[schema(value_type = String)]
+    pub accepted: BigDecimal,
+    #[schema(value_type = String)]
+    pub confirmed: BigDecimal,
+    /// Number of distinct nodes on the other side of reported agreements.
+    pub counterparties: i64,
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub payment_platforms: Vec<String>,
+    pub runtimes: Vec<String>,
+    pub score: StandardScore,
+}
+
+pub struct NodeScore {
+    pub role_id: String,
+    pub node_id: String,
+    pub standard_score: Option<BigDecimal>,
+}
+
+pub struct AgreementResult {
+    pub agreement_id: String,
+    pub p_id: String,
+    pub r_id: String,
+    pub agreement_result: String,
+}
+
+/// Scores of both sides of an agreement.
+pub struct AgreementScore {
+    pub p_id: String,
+    pub r_id: String,
+    pub p_score: f64,
+    pub r_score: f64,
+}
+
+/// Rows removed by [`StatusDao::erase_node`].
+#[derive(Serialize, Deserialize, Debug, Default, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErasedNode {
+    /// Agreements the node reported or was reported as counterparty of.
+    pub agreements: u64,
+    pub statuses: u64,
+    pub details: u64,
+    pub payment_events: u64,
+    /// Whether retention aggregates of the node or its agreements were removed.
+    pub archive: bool,
+    /// Reports removed from retention archives, set by the caller holding the archive dir.
+    pub archived_reports: u64,
+    pub notes: u64,
+    /// Whether a ban or whitelisting of the node was removed.
+    pub moderation: bool,
+    pub score_overrides: u64,
+    /// Quarantine entries of the erased agreements.
+    pub quarantined: u64,
+    /// Webhooks filtering on the node.
+    pub webhooks: u64,
+    /// Deliveries of those webhooks and of any other naming the node.
+    pub webhook_deliveries: u64,
+}
+
+impl ErasedNode {
+    pub fn is_empty(&self) -> bool {
+        self.agreements == 0
+            && !self.archive
+            && self.archived_reports == 0
+            && self.notes == 0
+            && !self.moderation
+            && self.score_overrides == 0
+            && self.webhooks == 0
+            && self.webhook_deliveries == 0
+    }
+}
+
+/// Expired agreements folded by [`StatusDao::fold_expired_agreements`], rolled back unless
+/// committed.
+pub struct FoldedBatch {
+    tx: Transaction<'static, Postgres>,
+    score_cache: ScoreCache,
+    pub agreement_ids: Vec<String>,
+    /// Removed raw reports, one JSON object per row tagged with its table.
+    pub rows: Vec<String>,
+}
+
+impl FoldedBatch {
+    pub async fn commit(self) -> sqlx::Result<()> {
+        self.tx.commit().await?;
+        self.score_cache.invalidate();
+        Ok(())
+    }
+}
+
+/// Agreement as an edge of the provider-requestor graph.
+pub struct AgreementEdge {
+    pub agreement_id: String,
+    pub provider_id: String,
+    pub requestor_id: String,
+    pub payment_address: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    #[serde(skip)]
+    pub secret: String,
+    pub event_types: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<AgreementRole>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub threshold: Option<BigDecimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub classification: Option<AgreementClassification>,
+    pub created_by: String,
+    pub created_ts: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NewWebhook {
+    pub url: String,
+    /// Event types to deliver, all when empty.
+    #[serde(default)]
+    pub event_types: Vec<String>,
+    pub role: Option<AgreementRole>,
+    #[schema(value_type = Option<String>)]
+    pub node_id: Option<NodeId>,
+    /// Deliver `scoreChanged` only when the score crosses this value.
+    #[schema(value_type = Option<String>)]
+    pub threshold: Option<BigDecimal>,
+    /// Deliver `agreementClassified` only for this classification.
+    pub classification: Option<AgreementClassification>,
+    /// HMAC key for payload signatures, generated when not given.
+    pub secret: Option<String>,
+}
+
+struct WebhookRow {
+    id: i64,
+    url: String,
+    secret: String,
+    event_types: Vec<String>,
+    role_id: Option<String>,
+    node_id: Option<String>,
+    threshold: Option<BigDecimal>,
+    classification: Option<String>,
+    created_by: String,
+    created_ts: DateTime<Utc>,
+}
+
+impl From<WebhookRow> for Webhook {
+    fn from(row: WebhookRow) -> Self {
+        Webhook {
+            id: row.id,
+            url: row.url,
+            secret: row.secret,
+            event_types: row.event_types,
+            role: row.role_id.as_deref().and_then(role_from_db),
+            node_id: row.node_id,
+            threshold: row.threshold,
+            classification: row
+                .classification
+                .as_deref()
+                .map(AgreementClassification::from),
+            created_by: row.created_by,
+            created_ts: row.created_ts,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub event_type: String,
+    pub payload: String,
+    pub attempt: i32,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub delivered_ts: DateTime<Utc>,
+}
+
+/// Operator decision about a node, applies to both of its roles.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeStatus {
+    /// Reports of the node are ignored and it is never ranked.
+    Banned,
+    /// Agreements of the node are exempt from sybil and collusion flags.
+    Whitelisted,
+}
+
+impl NodeStatus {
+    fn as_db(&self) -> &'static str {
+        match self {
+            NodeStatus::Banned => "banned",
+            NodeStatus::Whitelisted => "whitelisted",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct NewNodeStatus {
+    pub status: NodeStatus,
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct NewQuarantine {
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct NewNote {
+    pub note: String,
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeNote {
+    pub id: i64,
+    pub node_id: String,
+    pub note: String,
+    pub created_by: String,
+    pub created_ts: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NewScoreOverride {
+    /// Standard score reported instead of the computed one.
+    #[schema(value_type = String)]
+    pub standard_score: BigDecimal,
+    pub reason: String,
+}
+
+/// Write operation recorded in the append-only `audit_log`.
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub id: i64,
+    pub operation: String,
+    /// What was written, e.g. `P/<node id>/<agreement id>` for reports.
+    pub target: String,
+    /// Admin principal, not set for node reports.
+    pub principal: Option<String>,
+    pub source_ip: Option<String>,
+    /// Request id assigned by the request tracing middleware.
+    pub request_id: Option<String>,
+    /// Hex encoded SHA-256 of the JSON payload, not set for requests without a body.
+    pub payload_sha256: Option<String>,
+    pub created_ts: DateTime<Utc>,
+}
+
+/// Audit log operations of the admin moderation endpoints.
+const MODERATION_OPERATIONS: &[&str] = &[
+    "deleteReport",
+    "quarantine",
+    "release",
+    "setNodeStatus",
+    "clearNodeStatus",
+    "addNote",
+    "overrideScore",
+    "removeOverride",
+];
+
+/// Audit log entry, stored by DAO writes in their transaction.
+#[derive(Clone)]
+pub struct NewAuditEntry<'a> {
+    pub operation: &'a str,
+    pub target: &'a str,
+    pub principal: Option<&'a str>,
+    pub source_ip: Option<&'a str>,
+    pub request_id: Option<&'a str>,
+    pub payload_sha256: Option<String>,
+}
+
+impl<'a> NewAuditEntry<'a> {
+    /// Entry of a write done by a command line tool rather than through the API.
+    pub fn cli(operation: &'a str, target: &'a str) -> Self {
+        NewAuditEntry {
+            operation,
+            target,
+            principal: Some("cli"),
+            source_ip: None,
+            request_id: None,
+            payload_sha256: None,
+        }
+    }
+
+    /// Sets the hash of `payload` serialized as JSON.
+    pub fn with_payload<T: Serialize>(mut self, payload: &T) -> serde_json::Result<Self> {
+        let payload = serde_json::to_vec(payload)?;
+        self.payload_sha256 = Some(hex::encode(Sha256::digest(&payload)));
+        Ok(self)
+    }
+}
+
+/// Conditions of an audit log query, unset ones match everything.
+#[derive(Default)]
+pub struct AuditFilter<'a> {
+    pub operation: Option<&'a str>,
+    pub principal: Option<&'a str>,
+    pub target: Option<&'a str>,
+}
+
+/// Conditions of a data export, unset ones match everything.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    pub role_id: Option<&'static str>,
+    /// Agreements created at or after.
+    pub from: Option<DateTime<Utc>>,
+    /// Agreements created before.
+    pub to: Option<DateTime<Utc>>,
+    pub payment_platform: Option<String>,
+}
+
+/// Agreement report joined with its details and classification.
+#[derive(Debug)]
+pub struct ExportedAgreement {
+    pub role_id: String,
+    pub node_id: String,
+    pub agreement_id: String,
+    pub peer_id: Option<String>,
+    /// Agreement creation, first report of the status when details are missing.
+    pub created_ts: DateTime<Utc>,
+    pub updated_ts: DateTime<Utc>,
+    pub valid_to: Option<DateTime<Utc>>,
+    pub runtime: Option<String>,
+    pub payment_platform: Option<String>,
+    pub payment_address: Option<String>,
+    pub subnet: Option<String>,
+    pub task_package: Option<String>,
+    /// Amounts are kept as decimal text so that no precision is lost.
+    pub requested: String,
+    pub accepted: String,
+    pub confirmed: String,
+    pub classification: Option<String>,
+    pub score_contribution: Option<f64>,
+    /// JSON array of the payment events, in the form of status reports.
+    pub payment_events: Option<String>,
+}
+
+/// Node score, within the payment platform when the export is filtered by one.
+#[derive(Debug)]
+pub struct ExportedScore {
+    pub role_id: String,
+    pub node_id: String,
+    pub payment_platform: Option<String>,
+    pub raw_score: Option<f64>,
+    pub standard_score: Option<f64>,
+}
+
+/// Rows written by a bulk import, reports already stored are not counted.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImportedRows {
+    pub details: u64,
+    pub statuses: u64,
+    pub payment_events: u64,
+}
+
+impl std::ops::AddAssign for ImportedRows {
+    fn add_assign(&mut self, other: Self) {
+        self.details += other.details;
+        self.statuses += other.statuses;
+        self.payment_events += other.payment_events;
+    }
+}
+
+/// Ranks scores anew as part of a transaction changing bans or score overrides.
+async fn refresh_ranking(
+    tx: &mut Transaction<'_, Postgres>,
+    min_evidence: MinEvidence,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        "SELECT calc.refresh_ranking($1, $2)",
+        min_evidence.agreements,
+        min_evidence.counterparties
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+/// Appends an entry to the audit log as part of the transaction performing the write.
+async fn insert_audit_entry(
+    tx: &mut Transaction<'_, Postgres>,
+    entry: &NewAuditEntry<'_>,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO AUDIT_LOG(operation, target, principal, source_ip, request_id,
+            payload_sha256)
+        VALUES($1, $2, $3, $4, $5, $6)
+    "#,
+        entry.operation,
+        entry.target,
+        entry.principal,
+        entry.source_ip,
+        entry.request_id,
+        entry.payload_sha256
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
 }
 
 impl StatusDao {
     pub async fn connect(url: String) -> sqlx::Result<Self> {
+        Self::connect_with(&url, &PoolConfig::default()).await
+    }
+
+    /// Connects with pool size and timeouts from the configuration.
+    pub async fn connect_with(url: &str, config: &PoolConfig) -> sqlx::Result<Self> {
         log::debug!("connect to {}", url);
-        let pool = Pool::<Postgres>::connect(&url).await?;
-        Ok(StatusDao { pool })
+        let pool = pool_options(config).connect(url).await?;
+        Ok(StatusDao {
+            pool,
+            replica: None,
+            score_cache: ScoreCache::new(&ScoreCacheConfig::default()),
+            min_evidence: MinEvidence::default(),
+            webhooks: Default::default(),
+        })
+    }
+
+    /// Replaces the score cache, with a configured size and expiry.
+    pub fn with_score_cache(mut self, config: &ScoreCacheConfig) -> Self {
+        self.score_cache = ScoreCache::new(config);
+        self
+    }
+
+    /// Sets the evidence nodes need to be ranked, applied by the next ranking refresh.
+    pub fn with_min_evidence(mut self, min_evidence: MinEvidence) -> Self {
+        self.min_evidence = min_evidence;
+        self
+    }
+
+    /// Adds a read replica with the same pool settings as the primary.
+    ///
+    /// Connections are opened on first use, so an unreachable replica does not prevent the
+    /// start. Reads stay on the primary until [`StatusDao::check_replica`] finds it in sync.
+    pub fn with_replica(mut self, url: &str, config: &PoolConfig) -> sqlx::Result<Self> {
+        self.replica = Some(Replica {
+            pool: pool_options(config).connect_lazy(url)?,
+            healthy: Arc::new(AtomicBool::new(false)),
+        });
+        Ok(self)
+    }
+
+    /// Measures how far the replica is behind the primary, in seconds, and routes reads to it
+    /// only when that is within `max_lag`. The replica is unhealthy when it cannot be queried.
+    pub async fn check_replica(&self, max_lag: Duration) -> sqlx::Result<Option<f64>> {
+        let replica = match &self.replica {
+            Some(replica) => replica,
+            None => return Ok(None),
+        };
+        // The last replay time only says how old the last replayed transaction is, on a quiet
+        // primary it keeps aging while nothing is left to replay.
+        let lag = sqlx::query_scalar::<_, Option<f64>>(
+            r#"
+            SELECT CASE
+                WHEN NOT pg_is_in_recovery()
+                  OR pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() THEN 0
+                ELSE extract(epoch FROM now() - pg_last_xact_replay_timestamp())
+            END::float8"#,
+        )
+        .fetch_one(&replica.pool)
+        .await;
+        let healthy = matches!(lag, Ok(Some(lag)) if lag <= max_lag.as_secs_f64());
+        replica.healthy.store(healthy, Ordering::Relaxed);
+        lag
+    }
+
+    /// Pool for read-only queries that tolerate the replication lag.
+    fn reader(&self) -> &PgPool {
+        match &self.replica {
+            Some(replica) if replica.healthy.load(Ordering::Relaxed) => &replica.pool,
+            _ => &self.pool,
+        }
+    }
+
+    /// Waits for connections in use to be returned and closes the pools.
+    pub async fn close(&self) {
+        if let Some(replica) = &self.replica {
+            replica.pool.close().await;
+        }
+        self.pool.close().await
     }
 
     pub async fn list(&self, role_id: &str) -> sqlx::Result<Vec<String>> {
@@ -45,7 +754,7 @@ impl StatusDao {
             "SELECT distinct node_id FROM AGREEMENT_STATUS where ROLE_ID = $1",
             role_id
         )
-        .fetch_all(&self.pool)
+        .fetch_all(self.reader())
         .await?;
 
         Ok(nodes.into_iter().map(|node| node.node_id).collect())
@@ -111,6 +820,8 @@ impl StatusDao {
             requested: BigDecimal,
             accepted: BigDecimal,
             confirmed: BigDecimal,
+            agreement_result: Option<String>,
+            score_contribution: Option<BigDecimal>,
         }
 
         let agreement_rows = sqlx::query_as!(
@@ -123,33 +834,43 @@ impl StatusDao {
                 s.updated_ts as updated_ts,
                 s.requested requested,
                 s.accepted accepted,
-                s.confirmed confirmed
+                s.confirmed confirmed,
+                c.agreement_result as "agreement_result?",
+                CASE WHEN s.role_id = 'P' THEN c.p_score ELSE c.r_score END as score_contribution
              FROM AGREEMENT_STATUS s left join AGREEMENT_DETAILS d
                on (s.role_id = d.role_id and s.node_id = d.node_id and s.agreement_id = d.agreement_id)
+             left join (SELECT DISTINCT agreement_id, agreement_result, p_score, r_score
+                          FROM CALC.AGREEMENT) c on (s.agreement_id = c.agreement_id)
              where s.ROLE_ID = $1 and s.NODE_ID=$2"#,
             role_id,
             node_id
         )
-        .fetch_all(&self.pool)
+        .fetch_all(self.reader())
         .await?;
 
-        Ok(agreement_rows
+        agreement_rows
             .into_iter()
             .map(|agreement_row: AgreementRow| {
                 Ok(Agreement {
                     agreement_id: agreement_row.agreement_id,
                     peer_id: agreement_row.peer_id.unwrap_or_default(),
-                    created_ts: DateTime::from_utc(agreement_row.created_ts, Utc),
+                    created_ts: Utc.from_utc_datetime(&agreement_row.created_ts),
                     status: StatusBuilder::default()
                         .requested(agreement_row.requested)
                         .accepted(agreement_row.accepted)
                         .confirmed(agreement_row.confirmed)
-                        .ts(DateTime::<Utc>::from_utc(agreement_row.updated_ts, Utc))
+                        .ts(Utc.from_utc_datetime(&agreement_row.updated_ts))
                         .build()
                         .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+                    classification: agreement_row
+                        .agreement_result
+                        .as_deref()
+                        .map(AgreementClassification::from)
+                        .unwrap_or(AgreementClassification::Unknown),
+                    score_contribution: agreement_row.score_contribution,
                 })
             })
-            .collect::<sqlx::Result<_>>()?)
+            .collect()
     }
 
     pub async fn insert_agreement(
@@ -158,8 +879,10 @@ impl StatusDao {
         node_id: NodeId,
         agreement_id: &str,
         agreement_info: AgreementInfo,
+        audit_entry: &NewAuditEntry<'_>,
     ) -> sqlx::Result<bool> {
-        let r = sqlx::query!(
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
             r#"
             INSERT INTO AGREEMENT_DETAILS(
                 role_id, node_id, agreement_id,
@@ -181,8 +904,10 @@ impl StatusDao {
             agreement_info.subnet,
             agreement_info.task_package
         )
-        .execute(&self.pool)
+        .execute(&mut tx)
         .await?;
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
 
         Ok(false)
     }
@@ -193,8 +918,9 @@ impl StatusDao {
         node_id: NodeId,
         agreement_id: &str,
         status: &Status,
+        audit_entry: &NewAuditEntry<'_>,
     ) -> sqlx::Result<bool> {
-        let mut connection = self.pool.acquire().await?;
+        let mut tx = self.pool.begin().await?;
         let node_is_str = node_id.to_string();
         let _query = sqlx::query!(
             r#"
@@ -218,9 +944,31 @@ impl StatusDao {
             status.confirmed,
             status.ts
         )
-        .execute(&mut connection)
+        .execute(&mut tx)
         .await?;
 
+        for event in status.payment.iter().flat_map(|payment| &payment.events) {
+            sqlx::query!(
+                r#"
+                INSERT INTO AGREEMENT_PAYMENT_EVENT(role_id, node_id, agreement_id,
+                document_id, document_type, event_type, amount, event_ts)
+                VALUES($1, $2, $3, $4, $5, $6, $7, $8)
+                ON CONFLICT(role_id, node_id, agreement_id, document_id, event_type)
+                DO NOTHING
+            "#,
+                role,
+                &node_is_str,
+                agreement_id,
+                event.document_id,
+                document_type_as_db(event.document_type),
+                event_type_as_db(event.event_type),
+                event.amount,
+                event.ts
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
         let have_details: bool = sqlx::query_scalar!(
             r#"
             SELECT EXISTS(
@@ -234,33 +982,1350 @@ impl StatusDao {
             &node_is_str,
             agreement_id
         )
-        .fetch_one(&mut connection)
+        .fetch_one(&mut tx)
         .await?
         .unwrap_or_default();
 
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
         Ok(have_details)
     }
 
+    /// Returns `None` when the node has not reported any agreement status in the role.
+    pub async fn node_profile(
+        &self,
+        role: AgreementRole,
+        node_id: &str,
+    ) -> sqlx::Result<Option<NodeProfile>> {
+        struct TotalsRow {
+            agreements: i64,
+            requested: BigDecimal,
+            accepted: BigDecimal,
+            confirmed: BigDecimal,
+            first_seen: Option<NaiveDateTime>,
+            last_seen: Option<NaiveDateTime>,
+        }
+        struct DetailsRow {
+            counterparties: i64,
+            payment_platforms: Option<Vec<String>>,
+            runtimes: Option<Vec<String>>,
+        }
+
+        let role_id = role_as_db(role);
+        let totals = sqlx::query_as!(
+            TotalsRow,
+            r#"
+            SELECT count(*) AS "agreements!",
+                   coalesce(sum(requested), 0) AS "requested!",
+                   coalesce(sum(accepted), 0) AS "accepted!",
+                   coalesce(sum(confirmed), 0) AS "confirmed!",
+                   min(created_ts) AS first_seen,
+                   max(updated_ts) AS last_seen
+              FROM agreement_status
+             WHERE role_id = $1 AND node_id = $2"#,
+            role_id,
+            node_id
+        )
+        .fetch_one(self.reader())
+        .await?;
+        if totals.agreements == 0 {
+            return Ok(None);
+        }
+
+        let details = sqlx::query_as!(
+            DetailsRow,
+            r#"
+            SELECT count(DISTINCT peer_id) AS "counterparties!",
+                   array_agg(DISTINCT payment_platform::text)
+                       FILTER (WHERE payment_platform IS NOT NULL) AS payment_platforms,
+                   array_agg(DISTINCT runtime::text) FILTER (WHERE runtime IS NOT NULL) AS runtimes
+              FROM agreement_details
+             WHERE role_id = $1 AND node_id = $2"#,
+            role_id,
+            node_id
+        )
+        .fetch_one(self.reader())
+        .await?;
+
+        let mut classifications: BTreeMap<String, i64> = sqlx::query!(
+            r#"
+            SELECT c.agreement_result AS "agreement_result!",
+                   count(DISTINCT s.agreement_id) AS "count!"
+              FROM agreement_status s
+              JOIN calc.agreement c ON s.agreement_id = c.agreement_id
+             WHERE s.role_id = $1 AND s.node_id = $2
+             GROUP BY 1"#,
+            role_id,
+            node_id
+        )
+        .fetch_all(self.reader())
+        .await?
+        .into_iter()
+        .fold(BTreeMap::new(), |mut counts, row| {
+            let classification = AgreementClassification::from(row.agreement_result.as_str());
+            *counts
+                .entry(classification.as_str().to_string())
+                .or_default() += row.count;
+            counts
+        });
+        let unclassified = totals.agreements - classifications.values().sum::<i64>();
+        if unclassified > 0 {
+            *classifications
+                .entry(AgreementClassification::Unknown.as_str().to_string())
+                .or_default() += unclassified;
+        }
+
+        let score = self
+            .standard_score(role_id, node_id, ScoringAlgorithm::Standard, None)
+            .await?;
+
+        Ok(Some(NodeProfile {
+            node_id: node_id.to_string(),
+            role,
+            agreements: totals.agreements,
+            classifications,
+            requested: totals.requested,
+            accepted: totals.accepted,
+            confirmed: totals.confirmed,
+            counterparties: details.counterparties,
+            first_seen: totals.first_seen.map(|ts| Utc.from_utc_datetime(&ts)),
+            last_seen: totals.last_seen.map(|ts| Utc.from_utc_datetime(&ts)),
+            payment_platforms: details.payment_platforms.unwrap_or_default(),
+            runtimes: details.runtimes.unwrap_or_default(),
+            score,
+        }))
+    }
+
     pub async fn standard_score(
         &self,
         role_id: &str,
         node_id: &str,
+        algorithm: ScoringAlgorithm,
+        segment: Option<&Segment>,
     ) -> sqlx::Result<StandardScore> {
-        let standard_score = sqlx::query_as!(
-            StandardScore,
-            r#"SELECT CALC.STANDARD_SCORE($1, $2) as score"#,
+        let (segment_type, segment) = Segment::bind_values(algorithm, segment);
+        let key = ScoreKey {
+            role_id: role_id.to_string(),
+            node_id: node_id.to_string(),
+            segment_type,
+            segment: segment.to_string(),
+        };
+        if let Some(score) = self.score_cache.get(&key) {
+            return Ok(score);
+        }
+        let generation = self.score_cache.generation();
+        let row = sqlx::query_as!(
+            ScoreRow,
+            r#"
+            SELECT node_id AS "node_id!", score, agreements AS "agreements!",
+                counterparties AS "counterparties!", margin, sufficient AS "sufficient!",
+                banned AS "banned!", overridden AS "overridden!", rank, percentile
+            FROM calc.scored_node($1, $2, $3, $4)"#,
+            role_id,
+            node_id,
+            segment_type,
+            segment
+        )
+        .fetch_optional(self.reader())
+        .await?;
+
+        let score = row.map_or_else(StandardScore::not_scored, StandardScore::from);
+        self.score_cache.insert(key, generation, &score);
+        Ok(score)
+    }
+
+    /// Nodes with sufficient evidence ordered by standard score, best first unless
+    /// `worst_first` is set.
+    ///
+    /// `min_agreements` only filters the listing, ranks and percentiles are computed
+    /// among all nodes meeting the minimum evidence.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn ranking(
+        &self,
+        role_id: &str,
+        algorithm: ScoringAlgorithm,
+        segment: Option<&Segment>,
+        min_agreements: i64,
+        worst_first: bool,
+        start: i64,
+        limit: i64,
+    ) -> sqlx::Result<Vec<RankedNode>> {
+        let order = if worst_first { "DESC" } else { "ASC" };
+        let (segment_type, segment) = Segment::bind_values(algorithm, segment);
+        let rows = sqlx::query_as::<_, ScoreRow>(&format!(
+            r#"
+            SELECT node_id, score, agreements, counterparties, margin, sufficient,
+                   banned, overridden, rank, percentile
+              FROM calc.ranked_score
+             WHERE (segment_type, segment, role_id) = ($1, $2, $3)
+               AND sufficient AND agreements >= $4
+             ORDER BY rank {order}, node_id
+            OFFSET $5
+             LIMIT $6"#
+        ))
+        .bind(segment_type)
+        .bind(segment)
+        .bind(role_id)
+        .bind(min_agreements)
+        .bind(start)
+        .bind(limit)
+        .fetch_all(self.reader())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| RankedNode {
+                node_id: row.node_id.clone(),
+                score: row.into(),
+            })
+            .collect())
+    }
+
+    pub async fn node_scores(&self) -> sqlx::Result<Vec<NodeScore>> {
+        sqlx::query_as!(
+            NodeScore,
+            r#"
+            SELECT role_id AS "role_id!", node_id AS "node_id!", standard_score
+            FROM calc.node_score
+        "#
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn agreement_results(&self) -> sqlx::Result<Vec<AgreementResult>> {
+        sqlx::query_as!(
+            AgreementResult,
+            r#"
+            SELECT agreement_id AS "agreement_id!", p_id AS "p_id!", r_id AS "r_id!",
+                agreement_result AS "agreement_result!"
+            FROM calc.agreement
+        "#
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn agreement_scores(&self) -> sqlx::Result<Vec<AgreementScore>> {
+        sqlx::query_as!(
+            AgreementScore,
+            r#"
+            SELECT p_id AS "p_id!", r_id AS "r_id!", p_score::float8 AS "p_score!",
+                r_score::float8 AS "r_score!"
+            FROM calc.agreement
+            UNION ALL
+            SELECT p_id, r_id, p_score::float8, r_score::float8 FROM AGREEMENT_ARCHIVE
+        "#
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Replaces all counterparty-weighted scores with a new computation.
+    pub async fn replace_trust_scores(&self, scores: &[TrustScore]) -> sqlx::Result<()> {
+        let role_ids: Vec<&str> = scores.iter().map(|score| score.role_id).collect();
+        let node_ids: Vec<&str> = scores.iter().map(|score| score.node_id.as_str()).collect();
+        let trusts: Vec<f64> = scores.iter().map(|score| score.trust).collect();
+        let raw_scores: Vec<f64> = scores.iter().map(|score| score.raw_score).collect();
+        let standard_scores: Vec<Option<f64>> =
+            scores.iter().map(|score| score.standard_score).collect();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!("DELETE FROM TRUST_SCORE")
+            .execute(&mut tx)
+            .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO TRUST_SCORE(role_id, node_id, trust, raw_score, standard_score)
+            SELECT role_id, node_id, trust, round(raw_score::numeric, 8),
+                round(standard_score::numeric, 8)
+            FROM UNNEST($1::char[], $2::varchar[], $3::float8[], $4::float8[], $5::float8[])
+                AS t(role_id, node_id, trust, raw_score, standard_score)
+        "#,
+            &role_ids as &[&str],
+            &node_ids as &[&str],
+            &trusts,
+            &raw_scores,
+            &standard_scores as &[Option<f64>]
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        self.score_cache.invalidate();
+        Ok(())
+    }
+
+    /// One edge per agreement, the provider's report wins when both sides reported it.
+    /// Agreements of whitelisted nodes are left out.
+    pub async fn agreement_edges(&self) -> sqlx::Result<Vec<AgreementEdge>> {
+        sqlx::query_as!(
+            AgreementEdge,
+            r#"
+            SELECT DISTINCT ON (agreement_id) agreement_id,
+                CASE WHEN role_id = 'P' THEN node_id ELSE peer_id END AS "provider_id!",
+                CASE WHEN role_id = 'R' THEN node_id ELSE peer_id END AS "requestor_id!",
+                lower(payment_address) AS payment_address
+            FROM AGREEMENT_DETAILS
+            WHERE NOT EXISTS (
+                SELECT 1 FROM NODE_MODERATION
+                WHERE status = 'whitelisted' AND node_id IN (
+                    AGREEMENT_DETAILS.node_id, AGREEMENT_DETAILS.peer_id
+                )
+            )
+            ORDER BY agreement_id, role_id
+        "#
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Replaces all agreement weights with the result of the latest analysis.
+    pub async fn replace_agreement_flags(
+        &self,
+        flags: &BTreeMap<String, AgreementFlag>,
+    ) -> sqlx::Result<()> {
+        let agreement_ids: Vec<&str> = flags.keys().map(String::as_str).collect();
+        let weights: Vec<f64> = flags.values().map(|flag| flag.weight).collect();
+        let reasons: Vec<&str> = flags.values().map(|flag| flag.kind.as_db()).collect();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!("DELETE FROM AGREEMENT_FLAG")
+            .execute(&mut tx)
+            .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO AGREEMENT_FLAG(agreement_id, weight, reason)
+            SELECT * FROM UNNEST($1::varchar[], $2::float8[], $3::varchar[])
+        "#,
+            &agreement_ids as &[&str],
+            &weights,
+            &reasons as &[&str]
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await
+    }
+
+    /// `audit_entry` is recorded with the id of the new webhook as target.
+    pub async fn insert_webhook(
+        &self,
+        webhook: &NewWebhook,
+        secret: &str,
+        created_by: &str,
+        audit_entry: &NewAuditEntry<'_>,
+    ) -> sqlx::Result<Webhook> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query_as!(
+            WebhookRow,
+            r#"
+            INSERT INTO WEBHOOK(url, secret, event_types, role_id, node_id,
+                threshold, classification, created_by)
+            VALUES($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id, url, secret, event_types, role_id, node_id,
+                threshold, classification, created_by, created_ts
+        "#,
+            webhook.url,
+            secret,
+            &webhook.event_types,
+            webhook.role.map(role_as_db),
+            webhook.node_id.map(|node_id| node_id.to_string()),
+            webhook.threshold,
+            webhook.classification.map(|c| c.as_str()),
+            created_by
+        )
+        .fetch_one(&mut tx)
+        .await?;
+        let target = row.id.to_string();
+        let audit_entry = NewAuditEntry {
+            target: &target,
+            ..audit_entry.clone()
+        };
+        insert_audit_entry(&mut tx, &audit_entry).await?;
+        tx.commit().await?;
+        self.invalidate_webhooks().await;
+        Ok(row.into())
+    }
+
+    /// Webhooks for event delivery, loaded at most once per [`WEBHOOK_CACHE_TTL`] unless one
+    /// is added or removed. The expiry picks up changes made by other server instances.
+    pub async fn cached_webhooks(&self) -> sqlx::Result<Arc<Vec<Webhook>>> {
+        // Held while loading, so an invalidation waits and then drops what was loaded.
+        let mut cached = self.webhooks.lock().await;
+        match &*cached {
+            Some((loaded, webhooks)) if loaded.elapsed() < WEBHOOK_CACHE_TTL => {
+                Ok(webhooks.clone())
+            }
+            _ => {
+                let webhooks = Arc::new(self.list_webhooks().await?);
+                *cached = Some((Instant::now(), webhooks.clone()));
+                Ok(webhooks)
+            }
+        }
+    }
+
+    async fn invalidate_webhooks(&self) {
+        *self.webhooks.lock().await = None;
+    }
+
+    pub async fn list_webhooks(&self) -> sqlx::Result<Vec<Webhook>> {
+        let rows = sqlx::query_as!(
+            WebhookRow,
+            r#"
+            SELECT id, url, secret, event_types, role_id, node_id,
+                threshold, classification, created_by, created_ts
+            FROM WEBHOOK
+            ORDER BY id
+        "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(Webhook::from).collect())
+    }
+
+    pub async fn delete_webhook(
+        &self,
+        id: i64,
+        audit_entry: &NewAuditEntry<'_>,
+    ) -> sqlx::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = sqlx::query!("DELETE FROM WEBHOOK WHERE id = $1", id)
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Ok(false);
+        }
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
+        self.invalidate_webhooks().await;
+        Ok(true)
+    }
+
+    pub async fn insert_webhook_delivery(
+        &self,
+        webhook_id: i64,
+        event_type: &str,
+        payload: &str,
+        attempt: i32,
+        status_code: Option<i32>,
+        error: Option<&str>,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO WEBHOOK_DELIVERY(webhook_id, event_type, payload, attempt,
+                status_code, error)
+            VALUES($1, $2, $3, $4, $5, $6)
+        "#,
+            webhook_id,
+            event_type,
+            payload,
+            attempt,
+            status_code,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn list_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+        start: i64,
+        limit: i64,
+    ) -> sqlx::Result<Vec<WebhookDelivery>> {
+        sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+            SELECT id, event_type, payload, attempt, status_code, error, delivered_ts
+            FROM WEBHOOK_DELIVERY
+            WHERE webhook_id = $1
+            ORDER BY id DESC
+            OFFSET $2 LIMIT $3
+        "#,
+            webhook_id,
+            start,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Folds up to `limit` agreements without any report updated since `before` into
+    /// `agreement_archive` and removes their raw reports. Agreements missing in
+    /// `calc.agreement` are removed without a trace.
+    ///
+    /// Nothing is removed before [`FoldedBatch::commit`]. The batch is a repeatable read
+    /// transaction: reports stored after it started are kept, and it fails rather than remove
+    /// a report updated meanwhile.
+    pub async fn fold_expired_agreements(
+        &self,
+        before: NaiveDateTime,
+        limit: i64,
+    ) -> sqlx::Result<FoldedBatch> {
+        let mut tx = self.pool.begin().await?;
+        tx.execute("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+            .await?;
+        let agreement_ids = sqlx::query_scalar!(
+            r#"
+            SELECT agreement_id AS "agreement_id!"
+            FROM (
+                SELECT agreement_id, updated_ts AS ts FROM AGREEMENT_STATUS
+                UNION ALL
+                SELECT agreement_id, created_ts AT TIME ZONE 'UTC' FROM AGREEMENT_DETAILS
+                UNION ALL
+                SELECT agreement_id, event_ts AT TIME ZONE 'UTC' FROM AGREEMENT_PAYMENT_EVENT
+            ) reports
+            GROUP BY agreement_id
+            HAVING max(ts) < $1
+            ORDER BY agreement_id
+            LIMIT $2
+        "#,
+            before,
+            limit
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        let rows = sqlx::query_scalar!(
+            r#"
+            SELECT json_build_object('table', 'agreement_status', 'row', row_to_json(t))::text
+                AS "row!"
+            FROM AGREEMENT_STATUS t WHERE agreement_id = ANY($1)
+            UNION ALL
+            SELECT json_build_object('table', 'agreement_details', 'row', row_to_json(t))::text
+            FROM AGREEMENT_DETAILS t WHERE agreement_id = ANY($1)
+            UNION ALL
+            SELECT json_build_object('table', 'agreement_payment_event', 'row', row_to_json(t))::text
+            FROM AGREEMENT_PAYMENT_EVENT t WHERE agreement_id = ANY($1)
+        "#,
+            &agreement_ids
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO AGREEMENT_ARCHIVE(p_id, r_id, payment_platform, subnet, agreements,
+                p_score, r_score, p_score_sq, r_score_sq)
+            SELECT p_id, r_id, coalesce(payment_platform, ''), coalesce(subnet, ''), count(*),
+                sum(p_score), sum(r_score), sum(p_score * p_score), sum(r_score * r_score)
+            FROM calc.agreement
+            WHERE agreement_id = ANY($1)
+            GROUP BY 1, 2, 3, 4
+            ON CONFLICT (p_id, r_id, payment_platform, subnet) DO UPDATE SET
+                agreements = AGREEMENT_ARCHIVE.agreements + EXCLUDED.agreements,
+                p_score = AGREEMENT_ARCHIVE.p_score + EXCLUDED.p_score,
+                r_score = AGREEMENT_ARCHIVE.r_score + EXCLUDED.r_score,
+                p_score_sq = AGREEMENT_ARCHIVE.p_score_sq + EXCLUDED.p_score_sq,
+                r_score_sq = AGREEMENT_ARCHIVE.r_score_sq + EXCLUDED.r_score_sq
+        "#,
+            &agreement_ids
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM AGREEMENT_PAYMENT_EVENT WHERE agreement_id = ANY($1)",
+            &agreement_ids
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM AGREEMENT_STATUS WHERE agreement_id = ANY($1)",
+            &agreement_ids
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM AGREEMENT_DETAILS WHERE agreement_id = ANY($1)",
+            &agreement_ids
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM AGREEMENT_FLAG WHERE agreement_id = ANY($1)",
+            &agreement_ids
+        )
+        .execute(&mut tx)
+        .await?;
+        Ok(FoldedBatch {
+            tx,
+            score_cache: self.score_cache.clone(),
+            agreement_ids,
+            rows,
+        })
+    }
+
+    /// Removes everything stored about the node, including agreements it was only reported
+    /// as counterparty of.
+    pub async fn erase_node(
+        &self,
+        node_id: &str,
+        audit_entry: &NewAuditEntry<'_>,
+    ) -> sqlx::Result<ErasedNode> {
+        let mut tx = self.pool.begin().await?;
+        let agreement_ids = sqlx::query_scalar!(
+            r#"
+            SELECT agreement_id AS "agreement_id!"
+              FROM AGREEMENT_DETAILS WHERE node_id = $1 OR peer_id = $1
+            UNION
+            SELECT agreement_id FROM AGREEMENT_STATUS WHERE node_id = $1
+            UNION
+            SELECT agreement_id FROM AGREEMENT_PAYMENT_EVENT WHERE node_id = $1
+        "#,
+            node_id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+
+        let mut erased = ErasedNode {
+            agreements: agreement_ids.len() as u64,
+            ..ErasedNode::default()
+        };
+        erased.payment_events = sqlx::query!(
+            "DELETE FROM AGREEMENT_PAYMENT_EVENT WHERE agreement_id = ANY($1)",
+            &agreement_ids
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        erased.statuses = sqlx::query!(
+            "DELETE FROM AGREEMENT_STATUS WHERE agreement_id = ANY($1)",
+            &agreement_ids
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        erased.details = sqlx::query!(
+            "DELETE FROM AGREEMENT_DETAILS WHERE agreement_id = ANY($1)",
+            &agreement_ids
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        sqlx::query!(
+            "DELETE FROM AGREEMENT_FLAG WHERE agreement_id = ANY($1)",
+            &agreement_ids
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!("DELETE FROM TRUST_SCORE WHERE node_id = $1", node_id)
+            .execute(&mut tx)
+            .await?;
+        erased.archive = sqlx::query!("DELETE FROM NODE_ARCHIVE WHERE node_id = $1", node_id)
+            .execute(&mut tx)
+            .await?
+            .rows_affected()
+            + sqlx::query!(
+                "DELETE FROM AGREEMENT_ARCHIVE WHERE p_id = $1 OR r_id = $1",
+                node_id
+            )
+            .execute(&mut tx)
+            .await?
+            .rows_affected()
+            > 0;
+        sqlx::query!(
+            r#"
+            UPDATE NODE_ARCHIVE SET counterparties = array_remove(counterparties, $1)
+             WHERE $1 = ANY(counterparties)"#,
+            node_id
+        )
+        .execute(&mut tx)
+        .await?;
+        erased.quarantined = sqlx::query!(
+            "DELETE FROM AGREEMENT_QUARANTINE WHERE agreement_id = ANY($1)",
+            &agreement_ids
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        erased.notes = sqlx::query!("DELETE FROM NODE_NOTE WHERE node_id = $1", node_id)
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+        erased.moderation = sqlx::query!("DELETE FROM NODE_MODERATION WHERE node_id = $1", node_id)
+            .execute(&mut tx)
+            .await?
+            .rows_affected()
+            > 0;
+        erased.score_overrides =
+            sqlx::query!("DELETE FROM SCORE_OVERRIDE WHERE node_id = $1", node_id)
+                .execute(&mut tx)
+                .await?
+                .rows_affected();
+        erased.webhook_deliveries = sqlx::query!(
+            r#"
+            DELETE FROM WEBHOOK_DELIVERY
+             WHERE webhook_id IN (SELECT id FROM WEBHOOK WHERE node_id = $1)
+                OR position($1 in payload) > 0"#,
+            node_id
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        erased.webhooks = sqlx::query!("DELETE FROM WEBHOOK WHERE node_id = $1", node_id)
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+        if erased.is_empty() {
+            return Ok(erased);
+        }
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
+        self.score_cache.invalidate();
+        if erased.webhooks > 0 {
+            self.invalidate_webhooks().await;
+        }
+        Ok(erased)
+    }
+
+    /// Removes the report of one side of the agreement.
+    pub async fn delete_agreement_report(
+        &self,
+        role_id: &str,
+        node_id: &str,
+        agreement_id: &str,
+        audit_entry: &NewAuditEntry<'_>,
+    ) -> sqlx::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM AGREEMENT_PAYMENT_EVENT
+             WHERE role_id = $1 AND node_id = $2 AND agreement_id = $3"#,
             role_id,
+            node_id,
+            agreement_id
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected()
+            + sqlx::query!(
+                r#"
+                DELETE FROM AGREEMENT_STATUS
+                 WHERE role_id = $1 AND node_id = $2 AND agreement_id = $3"#,
+                role_id,
+                node_id,
+                agreement_id
+            )
+            .execute(&mut tx)
+            .await?
+            .rows_affected()
+            + sqlx::query!(
+                r#"
+                DELETE FROM AGREEMENT_DETAILS
+                 WHERE role_id = $1 AND node_id = $2 AND agreement_id = $3"#,
+                role_id,
+                node_id,
+                agreement_id
+            )
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Ok(false);
+        }
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Excludes the agreement from scoring until released, returns `false` for unknown ones.
+    pub async fn quarantine_agreement(
+        &self,
+        agreement_id: &str,
+        reason: Option<&str>,
+        principal: &str,
+        audit_entry: &NewAuditEntry<'_>,
+    ) -> sqlx::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO AGREEMENT_QUARANTINE(agreement_id, reason, created_by)
+            SELECT $1::varchar, $2::varchar, $3::varchar
+            WHERE EXISTS (SELECT 1 FROM AGREEMENT_STATUS WHERE agreement_id = $1)
+            ON CONFLICT (agreement_id) DO UPDATE SET
+                reason = EXCLUDED.reason,
+                created_by = EXCLUDED.created_by,
+                created_ts = CURRENT_TIMESTAMP
+        "#,
+            agreement_id,
+            reason,
+            principal
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        if inserted == 0 {
+            return Ok(false);
+        }
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn release_agreement(
+        &self,
+        agreement_id: &str,
+        audit_entry: &NewAuditEntry<'_>,
+    ) -> sqlx::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = sqlx::query!(
+            "DELETE FROM AGREEMENT_QUARANTINE WHERE agreement_id = $1",
+            agreement_id
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        if deleted == 0 {
+            return Ok(false);
+        }
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn set_node_status(
+        &self,
+        node_id: &str,
+        status: &NewNodeStatus,
+        principal: &str,
+        audit_entry: &NewAuditEntry<'_>,
+    ) -> sqlx::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO NODE_MODERATION(node_id, status, reason, created_by)
+            VALUES($1, $2, $3, $4)
+            ON CONFLICT (node_id) DO UPDATE SET
+                status = EXCLUDED.status,
+                reason = EXCLUDED.reason,
+                created_by = EXCLUDED.created_by,
+                created_ts = CURRENT_TIMESTAMP
+        "#,
+            node_id,
+            status.status.as_db(),
+            status.reason,
+            principal
+        )
+        .execute(&mut tx)
+        .await?;
+        refresh_ranking(&mut tx, self.min_evidence).await?;
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
+        self.score_cache.invalidate();
+        Ok(())
+    }
+
+    pub async fn clear_node_status(
+        &self,
+        node_id: &str,
+        audit_entry: &NewAuditEntry<'_>,
+    ) -> sqlx::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = sqlx::query!("DELETE FROM NODE_MODERATION WHERE node_id = $1", node_id)
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Ok(false);
+        }
+        refresh_ranking(&mut tx, self.min_evidence).await?;
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
+        self.score_cache.invalidate();
+        Ok(true)
+    }
+
+    pub async fn insert_node_note(
+        &self,
+        node_id: &str,
+        note: &str,
+        principal: &str,
+        audit_entry: &NewAuditEntry<'_>,
+    ) -> sqlx::Result<NodeNote> {
+        let mut tx = self.pool.begin().await?;
+        let note = sqlx::query_as!(
+            NodeNote,
+            r#"
+            INSERT INTO NODE_NOTE(node_id, note, created_by)
+            VALUES($1, $2, $3)
+            RETURNING id, node_id, note, created_by, created_ts
+        "#,
+            node_id,
+            note,
+            principal
+        )
+        .fetch_one(&mut tx)
+        .await?;
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
+        Ok(note)
+    }
+
+    pub async fn list_node_notes(&self, node_id: &str) -> sqlx::Result<Vec<NodeNote>> {
+        sqlx::query_as!(
+            NodeNote,
+            r#"
+            SELECT id, node_id, note, created_by, created_ts
+            FROM NODE_NOTE
+            WHERE node_id = $1
+            ORDER BY id
+        "#,
             node_id
         )
-        .fetch_one(&self.pool)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn set_score_override(
+        &self,
+        role_id: &str,
+        node_id: &str,
+        score_override: &NewScoreOverride,
+        principal: &str,
+        audit_entry: &NewAuditEntry<'_>,
+    ) -> sqlx::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO SCORE_OVERRIDE(role_id, node_id, standard_score, reason, created_by)
+            VALUES($1, $2, $3, $4, $5)
+            ON CONFLICT (role_id, node_id) DO UPDATE SET
+                standard_score = EXCLUDED.standard_score,
+                reason = EXCLUDED.reason,
+                created_by = EXCLUDED.created_by,
+                created_ts = CURRENT_TIMESTAMP
+        "#,
+            role_id,
+            node_id,
+            score_override.standard_score,
+            score_override.reason,
+            principal
+        )
+        .execute(&mut tx)
         .await?;
+        refresh_ranking(&mut tx, self.min_evidence).await?;
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
+        self.score_cache.invalidate();
+        Ok(())
+    }
+
+    pub async fn delete_score_override(
+        &self,
+        role_id: &str,
+        node_id: &str,
+        audit_entry: &NewAuditEntry<'_>,
+    ) -> sqlx::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = sqlx::query!(
+            "DELETE FROM SCORE_OVERRIDE WHERE role_id = $1 AND node_id = $2",
+            role_id,
+            node_id
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        if deleted == 0 {
+            return Ok(false);
+        }
+        refresh_ranking(&mut tx, self.min_evidence).await?;
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
+        self.score_cache.invalidate();
+        Ok(true)
+    }
+
+    /// Audit log entries of moderation actions, newest first.
+    pub async fn list_moderation_actions(
+        &self,
+        start: i64,
+        limit: i64,
+    ) -> sqlx::Result<Vec<AuditEntry>> {
+        sqlx::query_as!(
+            AuditEntry,
+            r#"
+            SELECT id, operation, target, principal, source_ip, request_id, payload_sha256,
+                created_ts
+            FROM AUDIT_LOG
+            WHERE operation = ANY($1)
+            ORDER BY created_ts DESC, id DESC
+            OFFSET $2 LIMIT $3
+        "#,
+            MODERATION_OPERATIONS as &[&str],
+            start,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Audit log entries matching the filter, newest first. Targets match by prefix.
+    pub async fn list_audit_entries(
+        &self,
+        filter: &AuditFilter<'_>,
+        start: i64,
+        limit: i64,
+    ) -> sqlx::Result<Vec<AuditEntry>> {
+        sqlx::query_as!(
+            AuditEntry,
+            r#"
+            SELECT id, operation, target, principal, source_ip, request_id, payload_sha256,
+                created_ts
+            FROM AUDIT_LOG
+            WHERE ($1::text IS NULL OR operation = $1)
+              AND ($2::text IS NULL OR principal = $2)
+              AND ($3::text IS NULL OR starts_with(target, $3))
+            ORDER BY id DESC
+            OFFSET $4 LIMIT $5
+        "#,
+            filter.operation,
+            filter.principal,
+            filter.target,
+            start,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Stores captured reports in a single transaction, bypassing events. The whole batch is
+    /// recorded as one audit log entry.
+    ///
+    /// Details already reported are kept, and a status only replaces a stored one reported at
+    /// the same time or earlier, so that the same data can be imported more than once.
+    pub async fn import_reports(
+        &self,
+        records: &[CaptureRecord],
+        audit_entry: &NewAuditEntry<'_>,
+    ) -> sqlx::Result<ImportedRows> {
+        let mut rows = ImportedRows::default();
+        let mut tx = self.pool.begin().await?;
+        for record in records {
+            let role_id = role_as_db(record.role);
+            let node_id = record.node_id.to_string();
+            match &record.request {
+                CapturedRequest::Agreement(info) => {
+                    rows.details += sqlx::query!(
+                        r#"
+                        INSERT INTO AGREEMENT_DETAILS(
+                            role_id, node_id, agreement_id,
+                            peer_id, created_ts, valid_to, runtime, payment_platform,
+                            payment_address, subnet, task_package)
+                        VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                        ON CONFLICT(role_id, node_id, agreement_id) DO NOTHING
+                    "#,
+                        role_id,
+                        node_id,
+                        record.agreement_id,
+                        info.peer_id.to_string(),
+                        info.created_ts,
+                        info.valid_to,
+                        info.runtime,
+                        info.payment_platform,
+                        info.payment_address,
+                        info.subnet,
+                        info.task_package
+                    )
+                    .execute(&mut tx)
+                    .await?
+                    .rows_affected();
+                }
+                CapturedRequest::Status(status) => {
+                    rows.statuses += sqlx::query!(
+                        r#"
+                        INSERT INTO AGREEMENT_STATUS(role_id, node_id, agreement_id, requested,
+                            accepted, confirmed, created_ts, updated_ts, reported_ts)
+                        VALUES($1, $2, $3, $4, $5, $6, $7 AT TIME ZONE 'UTC',
+                            $7 AT TIME ZONE 'UTC', $7)
+                        ON CONFLICT(role_id, node_id, agreement_id)
+                        DO UPDATE SET
+                            requested = $4,
+                            accepted = $5,
+                            confirmed = $6,
+                            updated_ts = $7 AT TIME ZONE 'UTC',
+                            reported_ts = $7
+                        WHERE AGREEMENT_STATUS.reported_ts IS NULL
+                           OR AGREEMENT_STATUS.reported_ts <= $7
+                    "#,
+                        role_id,
+                        node_id,
+                        record.agreement_id,
+                        status.requested,
+                        status.accepted,
+                        status.confirmed,
+                        status.ts
+                    )
+                    .execute(&mut tx)
+                    .await?
+                    .rows_affected();
+
+                    for event in status.payment.iter().flat_map(|payment| &payment.events) {
+                        rows.payment_events += sqlx::query!(
+                            r#"
+                            INSERT INTO AGREEMENT_PAYMENT_EVENT(role_id, node_id, agreement_id,
+                            document_id, document_type, event_type, amount, event_ts)
+                            VALUES($1, $2, $3, $4, $5, $6, $7, $8)
+                            ON CONFLICT(role_id, node_id, agreement_id, document_id, event_type)
+                            DO NOTHING
+                        "#,
+                            role_id,
+                            node_id,
+                            record.agreement_id,
+                            event.document_id,
+                            document_type_as_db(event.document_type),
+                            event_type_as_db(event.event_type),
+                            event.amount,
+                            event.ts
+                        )
+                        .execute(&mut tx)
+                        .await?
+                        .rows_affected();
+                    }
+                }
+            }
+        }
+        insert_audit_entry(&mut tx, audit_entry).await?;
+        tx.commit().await?;
+        Ok(rows)
+    }
+
+    /// Streams agreement reports in order of agreement creation, rows are fetched as the stream
+    /// is polled.
+    pub fn export_agreements<'a>(
+        &'a self,
+        filter: &'a ExportFilter,
+    ) -> BoxStream<'a, sqlx::Result<ExportedAgreement>> {
+        sqlx::query_as!(
+            ExportedAgreement,
+            r#"
+            SELECT
+                s.role_id, s.node_id, s.agreement_id, d.peer_id AS "peer_id?",
+                coalesce(d.created_ts, s.created_ts AT TIME ZONE 'UTC') AS "created_ts!",
+                s.updated_ts AT TIME ZONE 'UTC' AS "updated_ts!",
+                d.valid_to, d.runtime, d.payment_platform, d.payment_address, d.subnet,
+                d.task_package,
+                s.requested::text AS "requested!",
+                s.accepted::text AS "accepted!",
+                s.confirmed::text AS "confirmed!",
+                c.agreement_result AS "classification?",
+                (CASE WHEN s.role_id = 'P' THEN c.p_score ELSE c.r_score END)::float8
+                    AS score_contribution,
+                (SELECT json_agg(json_build_object(
+                            'documentId', e.document_id,
+                            'documentType', CASE e.document_type
+                                WHEN 'DEBIT_NOTE' THEN 'debitNote'
+                                ELSE lower(e.document_type) END,
+                            'eventType', lower(e.event_type),
+                            'amount', e.amount::text,
+                            'ts', e.event_ts)
+                        ORDER BY e.event_ts, e.document_id, e.event_type)::text
+                   FROM AGREEMENT_PAYMENT_EVENT e
+                  WHERE e.role_id = s.role_id AND e.node_id = s.node_id
+                    AND e.agreement_id = s.agreement_id) AS payment_events
+            FROM AGREEMENT_STATUS s
+            LEFT JOIN AGREEMENT_DETAILS d
+                ON (s.role_id = d.role_id AND s.node_id = d.node_id
+                    AND s.agreement_id = d.agreement_id)
+            LEFT JOIN (SELECT DISTINCT agreement_id, agreement_result, p_score, r_score
+                         FROM CALC.AGREEMENT) c ON (s.agreement_id = c.agreement_id)
+            WHERE ($1::text IS NULL OR s.role_id = $1)
+              AND ($2::timestamptz IS NULL
+                   OR coalesce(d.created_ts, s.created_ts AT TIME ZONE 'UTC') >= $2)
+              AND ($3::timestamptz IS NULL
+                   OR coalesce(d.created_ts, s.created_ts AT TIME ZONE 'UTC') < $3)
+              AND ($4::text IS NULL OR d.payment_platform = $4)
+            ORDER BY 5, s.agreement_id, s.role_id
+        "#,
+            filter.role_id,
+            filter.from,
+            filter.to,
+            filter.payment_platform.as_deref()
+        )
+        .fetch(self.reader())
+    }
+
+    /// Streams standard algorithm node scores, within the payment platform if the filter has
+    /// one. The date range does not apply to scores.
+    pub fn export_scores<'a>(
+        &'a self,
+        filter: &'a ExportFilter,
+    ) -> BoxStream<'a, sqlx::Result<ExportedScore>> {
+        sqlx::query_as!(
+            ExportedScore,
+            r#"
+            SELECT role_id AS "role_id!", node_id AS "node_id!",
+                NULL::text AS payment_platform,
+                raw_score::float8 AS raw_score, standard_score::float8 AS standard_score
+            FROM CALC.NODE_SCORE
+            WHERE $2::text IS NULL AND ($1::text IS NULL OR role_id = $1)
+            UNION ALL
+            SELECT role_id, node_id, segment,
+                raw_score::float8, standard_score::float8
+            FROM CALC.SEGMENT_SCORE
+            WHERE segment_type = 'payment_platform' AND segment = $2
+              AND ($1::text IS NULL OR role_id = $1)
+            ORDER BY 1, 2
+        "#,
+            filter.role_id,
+            filter.payment_platform.as_deref()
+        )
+        .fetch(self.reader())
+    }
+
+    /// Recalculates all aggregates in the `calc` schema.
+    pub async fn refresh_scores(&self) -> sqlx::Result<()> {
+        sqlx::query!("SELECT calc.refresh_all()")
+            .execute(&self.pool)
+            .await?;
+        self.score_cache.invalidate();
+        Ok(())
+    }
 
-        Ok(standard_score)
+    /// Recalculates evidence, ranks and percentiles of all scores, trust scores included.
+    pub async fn refresh_ranking(&self) -> sqlx::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        refresh_ranking(&mut tx, self.min_evidence).await?;
+        tx.commit().await?;
+        self.score_cache.invalidate();
+        Ok(())
+    }
+
+    /// (Re)creates the `calc` schema, dropping the previous aggregates.
+    pub async fn install_scoring(&self) -> sqlx::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        tx.execute(SCORING_SQL).await?;
+        // The script switches the session to the calc schema.
+        tx.execute("RESET search_path").await?;
+        tx.commit().await?;
+        self.score_cache.invalidate();
+        Ok(())
+    }
+
+    pub async fn scoring_installed(&self) -> sqlx::Result<bool> {
+        sqlx::query_scalar!(
+            r#"SELECT to_regproc('calc.refresh_ranking') IS NOT NULL AS "installed!""#
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+}
+
+fn pool_options(config: &PoolConfig) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .connect_timeout(Duration::from_secs(config.acquire_timeout))
+        .idle_timeout(config.idle_timeout.map(Duration::from_secs))
+        .max_lifetime(config.max_lifetime.map(Duration::from_secs))
+}
+
+/// Checks the replica lag periodically, logging when reads move between replica and primary.
+pub fn spawn_replica_monitor(dao: StatusDao, config: ReadReplicaConfig) {
+    actix_rt::spawn(async move {
+        let max_lag = Duration::from_secs(config.max_lag);
+        let mut interval = actix_rt::time::interval(Duration::from_secs(config.check_interval));
+        let mut was_healthy = None;
+        loop {
+            interval.tick().await;
+            let lag = dao.check_replica(max_lag).await;
+            let healthy =
+                matches!(&dao.replica, Some(replica) if replica.healthy.load(Ordering::Relaxed));
+            if was_healthy == Some(healthy) {
+                continue;
+            }
+            was_healthy = Some(healthy);
+            match lag {
+                Ok(Some(lag)) if healthy => {
+                    log::info!("read replica {:.1}s behind, routing reads to it", lag)
+                }
+                Ok(Some(lag)) => {
+                    log::warn!("read replica {:.1}s behind, routing reads to primary", lag)
+                }
+                Ok(None) => log::warn!("read replica lag unknown, routing reads to primary"),
+                Err(e) => log::warn!("read replica unavailable, routing reads to primary: {}", e),
+            }
+        }
+    });
+}
+
+pub fn role_as_db(role: AgreementRole) -> &'static str {
+    match role {
+        AgreementRole::Provider => "P",
+        AgreementRole::Requestor => "R",
+    }
+}
+
+pub fn role_from_db(role_id: &str) -> Option<AgreementRole> {
+    Some(match role_id {
+        "P" => AgreementRole::Provider,
+        "R" => AgreementRole::Requestor,
+        _ => return None,
+    })
+}
+
+fn document_type_as_db(document_type: DocumentType) -> &'static str {
+    match document_type {
+        DocumentType::DebitNote => "DEBIT_NOTE",
+        DocumentType::Invoice => "INVOICE",
+        DocumentType::Payment => "PAYMENT",
+    }
+}
+
+fn event_type_as_db(event_type: DocumentEventType) -> &'static str {
+    match event_type {
+        DocumentEventType::Issued => "ISSUED",
+        DocumentEventType::Accepted => "ACCEPTED",
+        DocumentEventType::Rejected => "REJECTED",
+        DocumentEventType::Settled => "SETTLED",
     }
 }
 
 pub async fn apply_migrations(database_url: &str) -> anyhow::Result<()> {
-    let pool = Pool::<Postgres>::connect(&database_url).await?;
+    let pool = Pool::<Postgres>::connect(database_url).await?;
     MIGRATOR.run(&pool).await?;
     Ok(())
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationState {
+    Pending,
+    Applied,
+    /// Applied, but the migration script has changed since.
+    Modified,
+}
+
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+}
+
+/// State of all migrations known to this build.
+pub async fn migration_status(database_url: &str) -> anyhow::Result<Vec<MigrationStatus>> {
+    let pool = Pool::<Postgres>::connect(database_url).await?;
+    let initialized = sqlx::query_scalar!(
+        r#"SELECT to_regclass('_sqlx_migrations') IS NOT NULL AS "initialized!""#
+    )
+    .fetch_one(&pool)
+    .await?;
+    let applied: HashMap<i64, Vec<u8>> = if initialized {
+        sqlx::query!("SELECT version, checksum FROM _sqlx_migrations WHERE success")
+            .fetch_all(&pool)
+            .await?
+            .into_iter()
+            .map(|row| (row.version, row.checksum))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    Ok(MIGRATOR
+        .iter()
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            state: match applied.get(&migration.version) {
+                None => MigrationState::Pending,
+                Some(checksum) if *checksum == *migration.checksum => MigrationState::Applied,
+                Some(_) => MigrationState::Modified,
+            },
+        })
+        .collect())
+}
@@ -0,0 +1,31 @@
+use reputation_aggregator_model::ReputationEvent;
+use tokio::sync::broadcast;
+
+/// Fan-out of reputation events to stream subscribers.
+///
+/// Slow subscribers lose the oldest events once `capacity` events are waiting for them.
+pub struct EventHub {
+    sender: broadcast::Sender<ReputationEvent>,
+}
+
+impl EventHub {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        EventHub { sender }
+    }
+
+    pub fn publish(&self, event: ReputationEvent) {
+        // no subscribers is not an error
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ReputationEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
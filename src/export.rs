@@ -0,0 +1,629 @@
+//! Streaming CSV and Parquet export of agreements and node scores.
+//!
+//! Rows are fetched from the database as the output is consumed and encoded in chunks, so an
+//! export of any size only ever holds one chunk in memory. A producer task encodes the rows and
+//! hands the chunks over a bounded channel, which stops it whenever the consumer falls behind.
+//!
+//! Parquet support pulls in arrow and is only built with the `export-parquet` feature.
+
+use crate::dao::{ExportFilter, ExportedAgreement, ExportedScore, StatusDao};
+use actix_web::web::Bytes;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::prelude::*;
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+/// CSV output is handed over once this many bytes are buffered.
+const CSV_CHUNK_SIZE: usize = 64 * 1024;
+/// Rows per Parquet row group.
+#[cfg(feature = "export-parquet")]
+const PARQUET_BATCH_ROWS: usize = 8192;
+/// Chunks encoded ahead of the consumer.
+const CHANNEL_CAPACITY: usize = 4;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(format!("unknown export format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dataset {
+    Agreements,
+    Scores,
+}
+
+impl Dataset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dataset::Agreements => "agreements",
+            Dataset::Scores => "scores",
+        }
+    }
+}
+
+impl FromStr for Dataset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "agreements" => Ok(Dataset::Agreements),
+            "scores" => Ok(Dataset::Scores),
+            _ => Err(format!("unknown dataset: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("failed to encode export: {0}")]
+    Encode(String),
+}
+
+/// Checks that the export can be produced, before any output is written.
+pub fn validate(
+    dataset: Dataset,
+    format: ExportFormat,
+    filter: &ExportFilter,
+) -> Result<(), String> {
+    if format == ExportFormat::Parquet && !cfg!(feature = "export-parquet") {
+        return Err("this server is built without parquet support".to_string());
+    }
+    if dataset == Dataset::Scores && (filter.from.is_some() || filter.to.is_some()) {
+        return Err("date range only applies to agreements".to_string());
+    }
+    Ok(())
+}
+
+/// Encoded export, ends with an error item if the export fails midway.
+pub fn export(
+    dao: StatusDao,
+    dataset: Dataset,
+    format: ExportFormat,
+    filter: ExportFilter,
+) -> impl Stream<Item = Result<Bytes, ExportError>> + 'static {
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    actix_rt::spawn(async move {
+        let result = match dataset {
+            Dataset::Agreements => produce(dao.export_agreements(&filter), format, &sender).await,
+            Dataset::Scores => produce(dao.export_scores(&filter), format, &sender).await,
+        };
+        if let Err(e) = result {
+            log::error!("{} export failed: {}", dataset.name(), e);
+            let _ = sender.send(Err(e)).await;
+        }
+    });
+    stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    })
+}
+
+/// Encodes rows until the stream ends or the consumer goes away.
+async fn produce<R: ExportRow>(
+    mut rows: BoxStream<'_, sqlx::Result<R>>,
+    format: ExportFormat,
+    sender: &mpsc::Sender<Result<Bytes, ExportError>>,
+) -> Result<(), ExportError> {
+    let mut encoder = Encoder::new(format, R::COLUMNS)?;
+    while let Some(row) = rows.try_next().await? {
+        encoder.push(row.values())?;
+        if let Some(chunk) = encoder.take()? {
+            if sender.send(Ok(chunk)).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+    let chunk = encoder.finish()?;
+    if !chunk.is_empty() {
+        let _ = sender.send(Ok(chunk)).await;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ColumnKind {
+    Text,
+    Float,
+    Timestamp,
+}
+
+#[derive(Debug)]
+struct Column {
+    name: &'static str,
+    #[cfg_attr(not(feature = "export-parquet"), allow(dead_code))]
+    kind: ColumnKind,
+}
+
+const fn column(name: &'static str, kind: ColumnKind) -> Column {
+    Column { name, kind }
+}
+
+/// Single exported value, variants match [`ColumnKind`].
+#[derive(Debug, Clone)]
+enum Value {
+    Text(Option<String>),
+    Float(Option<f64>),
+    Timestamp(Option<DateTime<Utc>>),
+}
+
+trait ExportRow {
+    const COLUMNS: &'static [Column];
+
+    fn values(self) -> Vec<Value>;
+}
+
+impl ExportRow for ExportedAgreement {
+    const COLUMNS: &'static [Column] = &[
+        column("role_id", ColumnKind::Text),
+        column("node_id", ColumnKind::Text),
+        column("agreement_id", ColumnKind::Text),
+        column("peer_id", ColumnKind::Text),
+        column("created_ts", ColumnKind::Timestamp),
+        column("updated_ts", ColumnKind::Timestamp),
+        column("valid_to", ColumnKind::Timestamp),
+        column("runtime", ColumnKind::Text),
+        column("payment_platform", ColumnKind::Text),
+        column("payment_address", ColumnKind::Text),
+        column("subnet", ColumnKind::Text),
+        column("task_package", ColumnKind::Text),
+        column("requested", ColumnKind::Text),
+        column("accepted", ColumnKind::Text),
+        column("confirmed", ColumnKind::Text),
+        column("classification", ColumnKind::Text),
+        column("score_contribution", ColumnKind::Float),
+        column("payment_events", ColumnKind::Text),
+    ];
+
+    fn values(self) -> Vec<Value> {
+        vec![
+            Value::Text(Some(self.role_id)),
+            Value::Text(Some(self.node_id)),
+            Value::Text(Some(self.agreement_id)),
+            Value::Text(self.peer_id),
+            Value::Timestamp(Some(self.created_ts)),
+            Value::Timestamp(Some(self.updated_ts)),
+            Value::Timestamp(self.valid_to),
+            Value::Text(self.runtime),
+            Value::Text(self.payment_platform),
+            Value::Text(self.payment_address),
+            Value::Text(self.subnet),
+            Value::Text(self.task_package),
+            Value::Text(Some(self.requested)),
+            Value::Text(Some(self.accepted)),
+            Value::Text(Some(self.confirmed)),
+            Value::Text(self.classification),
+            Value::Float(self.score_contribution),
+            Value::Text(self.payment_events),
+        ]
+    }
+}
+
+impl ExportRow for ExportedScore {
+    const COLUMNS: &'static [Column] = &[
+        column("role_id", ColumnKind::Text),
+        column("node_id", ColumnKind::Text),
+        column("payment_platform", ColumnKind::Text),
+        column("raw_score", ColumnKind::Float),
+        column("standard_score", ColumnKind::Float),
+    ];
+
+    fn values(self) -> Vec<Value> {
+        vec![
+            Value::Text(Some(self.role_id)),
+            Value::Text(Some(self.node_id)),
+            Value::Text(self.payment_platform),
+            Value::Float(self.raw_score),
+            Value::Float(self.standard_score),
+        ]
+    }
+}
+
+enum Encoder {
+    Csv(CsvEncoder),
+    #[cfg(feature = "export-parquet")]
+    Parquet(parquet_export::ParquetEncoder),
+}
+
+impl Encoder {
+    fn new(format: ExportFormat, columns: &'static [Column]) -> Result<Self, ExportError> {
+        match format {
+            ExportFormat::Csv => Ok(Encoder::Csv(CsvEncoder::new(columns))),
+            #[cfg(feature = "export-parquet")]
+            ExportFormat::Parquet => Ok(Encoder::Parquet(parquet_export::ParquetEncoder::new(
+                columns,
+            )?)),
+            #[cfg(not(feature = "export-parquet"))]
+            ExportFormat::Parquet => Err(ExportError::Encode(
+                "built without parquet support".to_string(),
+            )),
+        }
+    }
+
+    fn push(&mut self, values: Vec<Value>) -> Result<(), ExportError> {
+        match self {
+            Encoder::Csv(encoder) => {
+                encoder.push(&values);
+                Ok(())
+            }
+            #[cfg(feature = "export-parquet")]
+            Encoder::Parquet(encoder) => encoder.push(values),
+        }
+    }
+
+    /// Output encoded so far, once there is enough of it to be worth sending.
+    fn take(&mut self) -> Result<Option<Bytes>, ExportError> {
+        match self {
+            Encoder::Csv(encoder) => Ok(encoder.take()),
+            #[cfg(feature = "export-parquet")]
+            Encoder::Parquet(encoder) => encoder.take(),
+        }
+    }
+
+    fn finish(self) -> Result<Bytes, ExportError> {
+        match self {
+            Encoder::Csv(encoder) => Ok(encoder.finish()),
+            #[cfg(feature = "export-parquet")]
+            Encoder::Parquet(encoder) => encoder.finish(),
+        }
+    }
+}
+
+/// RFC 4180 CSV with a header line, timestamps in RFC 3339 and nulls as empty fields.
+struct CsvEncoder {
+    buffer: String,
+}
+
+impl CsvEncoder {
+    fn new(columns: &[Column]) -> Self {
+        let mut encoder = CsvEncoder {
+            buffer: String::with_capacity(CSV_CHUNK_SIZE),
+        };
+        let header: Vec<_> = columns
+            .iter()
+            .map(|column| Value::Text(Some(column.name.to_string())))
+            .collect();
+        encoder.push(&header);
+        encoder
+    }
+
+    fn push(&mut self, values: &[Value]) {
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                self.buffer.push(',');
+            }
+            match value {
+                Value::Text(Some(text)) => self.push_field(text),
+                Value::Float(Some(number)) => self.buffer.push_str(&number.to_string()),
+                Value::Timestamp(Some(ts)) => self
+                    .buffer
+                    .push_str(&ts.to_rfc3339_opts(SecondsFormat::Micros, true)),
+                Value::Text(None) | Value::Float(None) | Value::Timestamp(None) => (),
+            }
+        }
+        self.buffer.push_str("\r\n");
+    }
+
+    fn push_field(&mut self, text: &str) {
+        if text.contains(&[',', '"', '\r', '\n'][..]) {
+            self.buffer.push('"');
+            self.buffer.push_str(&text.replace('"', "\"\""));
+            self.buffer.push('"');
+        } else {
+            self.buffer.push_str(text);
+        }
+    }
+
+    fn take(&mut self) -> Option<Bytes> {
+        if self.buffer.len() < CSV_CHUNK_SIZE {
+            return None;
+        }
+        let chunk = std::mem::replace(&mut self.buffer, String::with_capacity(CSV_CHUNK_SIZE));
+        Some(Bytes::from(chunk))
+    }
+
+    fn finish(self) -> Bytes {
+        Bytes::from(self.buffer)
+    }
+}
+
+#[cfg(feature = "export-parquet")]
+mod parquet_export {
+    use super::{Column, ColumnKind, ExportError, Value, PARQUET_BATCH_ROWS};
+    use actix_web::web::Bytes;
+    use arrow::array::{ArrayRef, Float64Array, StringArray, TimestampMicrosecondArray};
+    use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+    use arrow::record_batch::RecordBatch;
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::properties::WriterProperties;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    /// Output of the writer, drained after every row group.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedBuffer {
+        fn drain(&self) -> Bytes {
+            let mut buffer = self.0.lock().unwrap();
+            Bytes::from(std::mem::take(&mut *buffer))
+        }
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Writes every [`PARQUET_BATCH_ROWS`] rows as a separate row group.
+    pub(super) struct ParquetEncoder {
+        columns: &'static [Column],
+        schema: SchemaRef,
+        rows: Vec<Vec<Value>>,
+        output: SharedBuffer,
+        writer: ArrowWriter<SharedBuffer>,
+    }
+
+    fn encode_error(e: impl std::fmt::Display) -> ExportError {
+        ExportError::Encode(e.to_string())
+    }
+
+    impl ParquetEncoder {
+        pub(super) fn new(columns: &'static [Column]) -> Result<Self, ExportError> {
+            let fields = columns
+                .iter()
+                .map(|column| {
+                    let data_type = match column.kind {
+                        ColumnKind::Text => DataType::Utf8,
+                        ColumnKind::Float => DataType::Float64,
+                        // UTC, like all timestamps in the database.
+                        ColumnKind::Timestamp => DataType::Timestamp(TimeUnit::Microsecond, None),
+                    };
+                    Field::new(column.name, data_type, true)
+                })
+                .collect();
+            let schema = Arc::new(Schema::new(fields));
+            let properties = WriterProperties::builder()
+                .set_max_row_group_size(PARQUET_BATCH_ROWS)
+                .build();
+            let output = SharedBuffer::default();
+            let writer = ArrowWriter::try_new(output.clone(), schema.clone(), Some(properties))
+                .map_err(encode_error)?;
+            Ok(ParquetEncoder {
+                columns,
+                schema,
+                rows: Vec::with_capacity(PARQUET_BATCH_ROWS),
+                output,
+                writer,
+            })
+        }
+
+        pub(super) fn push(&mut self, values: Vec<Value>) -> Result<(), ExportError> {
+            self.rows.push(values);
+            if self.rows.len() >= PARQUET_BATCH_ROWS {
+                self.write_batch()?;
+            }
+            Ok(())
+        }
+
+        fn write_batch(&mut self) -> Result<(), ExportError> {
+            let rows = std::mem::take(&mut self.rows);
+            let arrays = (0..self.columns.len())
+                .map(|i| column_array(self.columns[i].kind, rows.iter().map(|row| &row[i])))
+                .collect();
+            let batch = RecordBatch::try_new(self.schema.clone(), arrays).map_err(encode_error)?;
+            self.writer.write(&batch).map_err(encode_error)
+        }
+
+        pub(super) fn take(&mut self) -> Result<Option<Bytes>, ExportError> {
+            let chunk = self.output.drain();
+            Ok(Some(chunk).filter(|chunk| !chunk.is_empty()))
+        }
+
+        pub(super) fn finish(mut self) -> Result<Bytes, ExportError> {
+            if !self.rows.is_empty() {
+                self.write_batch()?;
+            }
+            self.writer.close().map_err(encode_error)?;
+            Ok(self.output.drain())
+        }
+    }
+
+    fn column_array<'a>(kind: ColumnKind, values: impl Iterator<Item = &'a Value>) -> ArrayRef {
+        match kind {
+            ColumnKind::Text => Arc::new(StringArray::from(
+                values
+                    .map(|value| match value {
+                        Value::Text(text) => text.as_deref(),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            )),
+            ColumnKind::Float => Arc::new(Float64Array::from(
+                values
+                    .map(|value| match value {
+                        Value::Float(number) => *number,
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            )),
+            ColumnKind::Timestamp => Arc::new(TimestampMicrosecondArray::from(
+                values
+                    .map(|value| match value {
+                        Value::Timestamp(ts) => ts.map(|ts| ts.timestamp_micros()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::CapturedRequest;
+    use crate::import::{ImportFormat, InputReader};
+
+    const COLUMNS: &[Column] = &[
+        column("text", ColumnKind::Text),
+        column("number", ColumnKind::Float),
+        column("ts", ColumnKind::Timestamp),
+    ];
+
+    fn ts(ts: &str) -> DateTime<Utc> {
+        ts.parse().unwrap()
+    }
+
+    #[test]
+    fn csv_encoder_quotes_fields_with_separators() {
+        let timestamp = ts("2022-05-10T12:00:00.0005Z");
+        let mut encoder = CsvEncoder::new(COLUMNS);
+        encoder.push(&[
+            Value::Text(Some("a,b".to_string())),
+            Value::Float(Some(1.5)),
+            Value::Timestamp(Some(timestamp)),
+        ]);
+        encoder.push(&[
+            Value::Text(Some("say \"hi\"\r\nbye".to_string())),
+            Value::Float(None),
+            Value::Timestamp(None),
+        ]);
+        encoder.push(&[
+            Value::Text(None),
+            Value::Float(Some(-2.0)),
+            Value::Timestamp(None),
+        ]);
+        assert_eq!(encoder.take(), None);
+        assert_eq!(
+            encoder.finish(),
+            "text,number,ts\r\n\
+             \"a,b\",1.5,2022-05-10T12:00:00.000500Z\r\n\
+             \"say \"\"hi\"\"\r\nbye\",,\r\n\
+             ,-2,\r\n"
+        );
+    }
+
+    #[test]
+    fn csv_encoder_hands_over_full_chunks() {
+        let mut encoder = CsvEncoder::new(COLUMNS);
+        let row = [
+            Value::Text(Some("x".repeat(1000))),
+            Value::Float(None),
+            Value::Timestamp(None),
+        ];
+        let chunk = loop {
+            encoder.push(&row);
+            if let Some(chunk) = encoder.take() {
+                break chunk;
+            }
+        };
+        assert!(chunk.starts_with(b"text,number,ts\r\n"));
+        assert!(chunk.len() >= CSV_CHUNK_SIZE && chunk.len() < CSV_CHUNK_SIZE + 1004);
+        assert_eq!(encoder.take(), None);
+        assert_eq!(encoder.finish(), "");
+    }
+
+    #[test]
+    fn exported_agreement_is_read_back_by_the_import() {
+        let created_ts = ts("2022-05-10T12:00:00Z");
+        let updated_ts = ts("2022-05-10T13:00:00.000001Z");
+        let agreement = ExportedAgreement {
+            role_id: "P".to_string(),
+            node_id: "0x1111111111111111111111111111111111111111".to_string(),
+            agreement_id: "a1".to_string(),
+            peer_id: Some("0x2222222222222222222222222222222222222222".to_string()),
+            created_ts,
+            updated_ts,
+            valid_to: None,
+            runtime: Some("vm".to_string()),
+            payment_platform: Some("erc20-polygon-glm".to_string()),
+            payment_address: Some("0x1111111111111111111111111111111111111111".to_string()),
+            subnet: Some("public, \"beta\"\nnet".to_string()),
+            task_package: None,
+            requested: "2.5".to_string(),
+            accepted: "2.5".to_string(),
+            confirmed: "1.000000000000000001".to_string(),
+            classification: Some("PAID".to_string()),
+            score_contribution: Some(2.5),
+            payment_events: Some(
+                r#"[{"documentId":"i1","documentType":"invoice","eventType":"accepted","amount":"2.5","ts":"2022-05-10T12:30:00+00:00"}]"#
+                    .to_string(),
+            ),
+        };
+        let mut encoder = CsvEncoder::new(ExportedAgreement::COLUMNS);
+        encoder.push(&agreement.values());
+        let csv = encoder.finish();
+
+        let mut reader = InputReader::new(&csv[..], ImportFormat::Csv).unwrap();
+        let (line, text) = reader.next_input().unwrap().unwrap();
+        assert_eq!(line, 2);
+        assert_eq!(reader.next_input().unwrap(), None);
+        let records = reader.parse(&text).unwrap();
+        assert_eq!(records.len(), 2);
+        match &records[0].request {
+            CapturedRequest::Agreement(info) => {
+                assert_eq!(records[0].ts, created_ts);
+                assert_eq!(
+                    info.peer_id.to_string(),
+                    "0x2222222222222222222222222222222222222222"
+                );
+                assert_eq!(info.runtime.as_deref(), Some("vm"));
+                assert_eq!(info.subnet.as_deref(), Some("public, \"beta\"\nnet"));
+                assert_eq!(info.task_package, None);
+            }
+            request => panic!("unexpected request {:?}", request),
+        }
+        match &records[1].request {
+            CapturedRequest::Status(status) => {
+                assert_eq!(status.ts, updated_ts);
+                assert_eq!(status.requested.to_string(), "2.5");
+                assert_eq!(status.confirmed.to_string(), "1.000000000000000001");
+                let events = &status.payment.as_ref().unwrap().events;
+                assert_eq!(events.len(), 1);
+                assert_eq!(events[0].document_id, "i1");
+                assert_eq!(events[0].ts, ts("2022-05-10T12:30:00Z"));
+            }
+            request => panic!("unexpected request {:?}", request),
+        }
+    }
+}
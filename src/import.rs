@@ -0,0 +1,510 @@
+//! Bulk import of agreement reports from files, bypassing the REST API.
+//!
+//! Two input formats are understood:
+//!
+//! * JSONL capture, one [`CaptureRecord`] per line, as written by the network simulator and
+//!   replayed by `capture-replay`,
+//! * CSV with a header line, in the layout of the agreements export (see [`crate::export`]).
+//!   A row carries the details when `peer_id` is set and the status when `requested` is set,
+//!   along with the payment events of `payment_events`, a JSON array of [`DocumentEvent`]s.
+//!
+//! Records are written in batched transactions, each recorded in the audit log with the input
+//! lines as target. Input that cannot be parsed, fails validation or is refused by the
+//! database is written to a reject file instead, one JSON object per input line or CSV row, and
+//! the import carries on.
+
+use crate::capture::{CaptureRecord, CapturedRequest};
+use crate::dao::{role_from_db, ImportedRows, NewAuditEntry, StatusDao};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, Utc};
+use reputation_aggregator_model::{
+    AgreementInfoBuilder, AgreementRole, DocumentEvent, NodeId, PaymentDetails, StatusBuilder,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Length of the `agreement_id` columns.
+const MAX_AGREEMENT_ID_LEN: usize = 120;
+/// Characters of the input name kept in audit log targets, which hold up to 200.
+const MAX_SOURCE_LEN: usize = 150;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Jsonl,
+    Csv,
+}
+
+impl ImportFormat {
+    /// Guesses the format from the file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "jsonl" => Some(ImportFormat::Jsonl),
+            "csv" => Some(ImportFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ImportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(ImportFormat::Jsonl),
+            "csv" => Ok(ImportFormat::Csv),
+            _ => Err(format!("unknown import format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Input lines or CSV rows read, not counting the header and empty lines.
+    pub inputs: usize,
+    pub rejected: usize,
+    pub rows: ImportedRows,
+}
+
+/// One input line or CSV row with the records parsed out of it.
+struct Input {
+    line: usize,
+    text: String,
+    records: Vec<CaptureRecord>,
+}
+
+#[derive(Serialize)]
+struct Reject<'a> {
+    line: usize,
+    error: &'a str,
+    input: &'a str,
+}
+
+/// `source` names the input in the audit log.
+pub async fn import(
+    dao: &StatusDao,
+    source: &str,
+    input: impl BufRead,
+    format: ImportFormat,
+    rejects: &mut impl Write,
+    batch_size: usize,
+) -> anyhow::Result<ImportSummary> {
+    let source: String = source.chars().take(MAX_SOURCE_LEN).collect();
+    let mut reader = InputReader::new(input, format)?;
+    let mut summary = ImportSummary::default();
+    let mut batch = Vec::with_capacity(batch_size);
+    while let Some((line, text)) = reader.next_input()? {
+        summary.inputs += 1;
+        match reader
+            .parse(&text)
+            .and_then(|records| validate(&records).map(|_| records))
+        {
+            Ok(records) => batch.push(Input {
+                line,
+                text,
+                records,
+            }),
+            Err(error) => reject(rejects, &mut summary, line, &error, &text)?,
+        }
+        if batch.len() >= batch_size {
+            store(dao, &source, &mut batch, rejects, &mut summary).await?;
+        }
+    }
+    store(dao, &source, &mut batch, rejects, &mut summary).await?;
+    rejects.flush()?;
+    Ok(summary)
+}
+
+/// Stores the batch in one transaction. When the database refuses it, inputs are stored one
+/// by one to find the offending ones.
+async fn store(
+    dao: &StatusDao,
+    source: &str,
+    batch: &mut Vec<Input>,
+    rejects: &mut impl Write,
+    summary: &mut ImportSummary,
+) -> anyhow::Result<()> {
+    let records: Vec<CaptureRecord> = batch
+        .iter()
+        .flat_map(|input| input.records.iter().cloned())
+        .collect();
+    let (first, last) = match (batch.first(), batch.last()) {
+        (Some(first), Some(last)) => (first.line, last.line),
+        _ => return Ok(()),
+    };
+    let target = format!("{}:{}-{}", source, first, last);
+    let audit_entry = NewAuditEntry::cli("importReports", &target).with_payload(&records)?;
+    match dao.import_reports(&records, &audit_entry).await {
+        Ok(rows) => summary.rows += rows,
+        Err(sqlx::Error::Database(e)) => {
+            log::warn!("import batch refused ({}), retrying one by one", e);
+            for input in batch.iter() {
+                let target = format!("{}:{}", source, input.line);
+                let audit_entry =
+                    NewAuditEntry::cli("importReports", &target).with_payload(&input.records)?;
+                match dao.import_reports(&input.records, &audit_entry).await {
+                    Ok(rows) => summary.rows += rows,
+                    Err(sqlx::Error::Database(e)) => {
+                        reject(rejects, summary, input.line, e.message(), &input.text)?
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Err(e) => return Err(e.into()),
+    }
+    batch.clear();
+    Ok(())
+}
+
+fn reject(
+    rejects: &mut impl Write,
+    summary: &mut ImportSummary,
+    line: usize,
+    error: &str,
+    input: &str,
+) -> io::Result<()> {
+    summary.rejected += 1;
+    let reject = Reject { line, error, input };
+    serde_json::to_writer(&mut *rejects, &reject)?;
+    rejects.write_all(b"\n")
+}
+
+fn validate(records: &[CaptureRecord]) -> Result<(), String> {
+    for record in records {
+        if record.agreement_id.is_empty() || record.agreement_id.len() > MAX_AGREEMENT_ID_LEN {
+            return Err(format!(
+                "agreement id must have 1 to {} characters",
+                MAX_AGREEMENT_ID_LEN
+            ));
+        }
+        if let CapturedRequest::Status(status) = &record.request {
+            let zero = BigDecimal::zero();
+            if status.requested < zero || status.accepted < zero || status.confirmed < zero {
+                return Err("amounts must not be negative".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) struct InputReader<R> {
+    reader: R,
+    format: ImportFormat,
+    line: usize,
+    /// CSV column positions by name.
+    columns: HashMap<String, usize>,
+}
+
+impl<R: BufRead> InputReader<R> {
+    pub(crate) fn new(reader: R, format: ImportFormat) -> anyhow::Result<Self> {
+        let mut input_reader = InputReader {
+            reader,
+            format,
+            line: 0,
+            columns: HashMap::new(),
+        };
+        if format == ImportFormat::Csv {
+            let (_, header) = input_reader
+                .next_input()?
+                .ok_or_else(|| anyhow::anyhow!("missing CSV header"))?;
+            let header = split_csv(&header)
+                .ok_or_else(|| anyhow::anyhow!("unterminated quoted field in CSV header"))?;
+            input_reader.columns = header
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| (name, i))
+                .collect();
+            for required in ["role_id", "node_id", "agreement_id"] {
+                if !input_reader.columns.contains_key(required) {
+                    anyhow::bail!("missing CSV column {}", required);
+                }
+            }
+        }
+        Ok(input_reader)
+    }
+
+    /// Next non-empty input with the number of its first line. A quoted CSV field can span
+    /// several lines.
+    pub(crate) fn next_input(&mut self) -> io::Result<Option<(usize, String)>> {
+        let mut text = String::new();
+        let mut first_line = self.line + 1;
+        loop {
+            let mut line = String::new();
+            // At the end of input an unterminated quote is left for the parser to reject.
+            let end = self.reader.read_line(&mut line)? == 0;
+            if end && text.is_empty() {
+                return Ok(None);
+            }
+            self.line += 1;
+            if text.is_empty() && line.trim().is_empty() {
+                first_line = self.line + 1;
+                continue;
+            }
+            text.push_str(&line);
+            if end || self.format == ImportFormat::Jsonl || split_csv(&text).is_some() {
+                let len = text.trim_end_matches(&['\r', '\n'][..]).len();
+                text.truncate(len);
+                return Ok(Some((first_line, text)));
+            }
+        }
+    }
+
+    pub(crate) fn parse(&self, text: &str) -> Result<Vec<CaptureRecord>, String> {
+        match self.format {
+            ImportFormat::Jsonl => serde_json::from_str(text)
+                .map(|record| vec![record])
+                .map_err(|e| e.to_string()),
+            ImportFormat::Csv => {
+                let fields = split_csv(text).ok_or("unterminated quoted field")?;
+                CsvRow {
+                    columns: &self.columns,
+                    fields,
+                }
+                .records()
+            }
+        }
+    }
+}
+
+/// Splits a CSV record into fields, `None` while a quoted field is still open.
+fn split_csv(text: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') => quoted = true,
+            (false, ',') => fields.push(std::mem::take(&mut field)),
+            (false, '\r') | (false, '\n') => (),
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields)
+}
+
+struct CsvRow<'a> {
+    columns: &'a HashMap<String, usize>,
+    fields: Vec<String>,
+}
+
+impl CsvRow<'_> {
+    /// Field of the column, empty fields and missing columns are `None`.
+    fn get(&self, column: &str) -> Option<&str> {
+        let field = self.fields.get(*self.columns.get(column)?)?;
+        Some(field.as_str()).filter(|field| !field.is_empty())
+    }
+
+    fn require(&self, column: &str) -> Result<&str, String> {
+        self.get(column).ok_or(format!("missing {}", column))
+    }
+
+    fn parse<T: FromStr>(&self, column: &str) -> Result<Option<T>, String>
+    where
+        T::Err: std::fmt::Display,
+    {
+        self.get(column)
+            .map(|field| {
+                field
+                    .parse()
+                    .map_err(|e| format!("invalid {}: {}", column, e))
+            })
+            .transpose()
+    }
+
+    fn text(&self, column: &str) -> Option<String> {
+        self.get(column).map(str::to_string)
+    }
+
+    fn records(&self) -> Result<Vec<CaptureRecord>, String> {
+        if self.fields.len() != self.columns.len() {
+            return Err(format!(
+                "expected {} fields, found {}",
+                self.columns.len(),
+                self.fields.len()
+            ));
+        }
+        let role = parse_role(self.require("role_id")?)?;
+        let node_id = self
+            .require("node_id")?
+            .parse()
+            .map_err(|e| format!("invalid node_id: {}", e))?;
+        let agreement_id = self.require("agreement_id")?.to_string();
+        let record = |ts, request| CaptureRecord {
+            ts,
+            role,
+            node_id,
+            agreement_id: agreement_id.clone(),
+            request,
+        };
+
+        let mut records = Vec::new();
+        if let Some(peer_id) = self.get("peer_id") {
+            let created_ts: DateTime<Utc> = self
+                .parse("created_ts")?
+                .ok_or_else(|| "missing created_ts".to_string())?;
+            let info = AgreementInfoBuilder::default()
+                .peer_id(
+                    peer_id
+                        .parse::<NodeId>()
+                        .map_err(|e| format!("invalid peer_id: {}", e))?,
+                )
+                .created_ts(created_ts)
+                .valid_to(self.parse::<DateTime<Utc>>("valid_to")?)
+                .runtime(self.text("runtime"))
+                .payment_platform(self.require("payment_platform")?)
+                .payment_address(self.require("payment_address")?)
+                .subnet(self.text("subnet"))
+                .task_package(self.text("task_package"))
+                .build()
+                .map_err(|e| e.to_string())?;
+            records.push(record(created_ts, CapturedRequest::Agreement(info)));
+        }
+        if let Some(requested) = self.parse::<BigDecimal>("requested")? {
+            let ts: DateTime<Utc> = self
+                .parse("updated_ts")?
+                .ok_or_else(|| "missing updated_ts".to_string())?;
+            let events = self
+                .get("payment_events")
+                .map(serde_json::from_str::<Vec<DocumentEvent>>)
+                .transpose()
+                .map_err(|e| format!("invalid payment_events: {}", e))?;
+            let status = StatusBuilder::default()
+                .requested(requested)
+                .accepted(self.parse::<BigDecimal>("accepted")?.unwrap_or_default())
+                .confirmed(self.parse::<BigDecimal>("confirmed")?.unwrap_or_default())
+                .ts(ts)
+                .payment(events.map(PaymentDetails::from))
+                .build()
+                .map_err(|e| e.to_string())?;
+            records.push(record(ts, CapturedRequest::Status(status)));
+        }
+        if records.is_empty() {
+            return Err("neither details nor status in the row".to_string());
+        }
+        Ok(records)
+    }
+}
+
+/// Accepts both the `P`/`R` of exports and the role names of the API.
+fn parse_role(role: &str) -> Result<AgreementRole, String> {
+    match role {
+        "provider" => Ok(AgreementRole::Provider),
+        "requestor" => Ok(AgreementRole::Requestor),
+        _ => role_from_db(role).ok_or(format!("invalid role_id: {}", role)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NODE: &str = "0x1111111111111111111111111111111111111111";
+
+    fn csv_reader(csv: &str) -> InputReader<&[u8]> {
+        InputReader::new(csv.as_bytes(), ImportFormat::Csv).unwrap()
+    }
+
+    #[test]
+    fn split_csv_unquotes_fields() {
+        assert_eq!(
+            split_csv("a,\"b,c\",\"say \"\"hi\"\"\",,\"x\r\ny\"\r\n"),
+            Some(vec![
+                "a".to_string(),
+                "b,c".to_string(),
+                "say \"hi\"".to_string(),
+                String::new(),
+                "x\r\ny".to_string(),
+            ])
+        );
+        assert_eq!(split_csv("a,\"b\nc"), None);
+    }
+
+    #[test]
+    fn next_input_joins_the_lines_of_quoted_fields() {
+        let csv = format!(
+            "role_id,node_id,agreement_id\n\nP,{0},\"a,\nb\"\r\n\nR,{0},c\n",
+            NODE
+        );
+        let mut reader = csv_reader(&csv);
+        assert_eq!(
+            reader.next_input().unwrap(),
+            Some((3, format!("P,{},\"a,\nb\"", NODE)))
+        );
+        assert_eq!(
+            reader.next_input().unwrap(),
+            Some((6, format!("R,{},c", NODE)))
+        );
+        assert_eq!(reader.next_input().unwrap(), None);
+    }
+
+    #[test]
+    fn unterminated_quote_at_end_of_input_is_rejected() {
+        let csv = format!("role_id,node_id,agreement_id\nP,{},\"open\nrest", NODE);
+        let mut reader = csv_reader(&csv);
+        let (line, text) = reader.next_input().unwrap().unwrap();
+        assert_eq!(
+            (line, text.as_str()),
+            (2, format!("P,{},\"open\nrest", NODE).as_str())
+        );
+        assert_eq!(
+            reader.parse(&text).unwrap_err(),
+            "unterminated quoted field"
+        );
+        assert_eq!(reader.next_input().unwrap(), None);
+    }
+
+    #[test]
+    fn records_require_every_column() {
+        let reader = csv_reader("role_id,node_id,agreement_id,requested,updated_ts\n");
+        assert_eq!(
+            reader.parse(&format!("P,{},a1,1", NODE)).unwrap_err(),
+            "expected 5 fields, found 4"
+        );
+    }
+
+    #[test]
+    fn records_read_the_status_with_its_payment_events() {
+        let reader =
+            csv_reader("role_id,node_id,agreement_id,requested,updated_ts,payment_events\n");
+        let events = r#"[{"documentId":"i1","documentType":"invoice","eventType":"settled","amount":"2.5","ts":"2022-05-10T12:00:00Z"}]"#;
+        let row = format!(
+            "provider,{},a1,2.5,2022-05-10T13:00:00Z,\"{}\"",
+            NODE,
+            events.replace('"', "\"\"")
+        );
+
+        let records = reader.parse(&row).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].role, AgreementRole::Provider);
+        match &records[0].request {
+            CapturedRequest::Status(status) => {
+                assert_eq!(status.requested, BigDecimal::from_str("2.5").unwrap());
+                assert_eq!(status.confirmed, BigDecimal::zero());
+                let events = &status.payment.as_ref().unwrap().events;
+                assert_eq!(events.len(), 1);
+                assert_eq!(events[0].document_id, "i1");
+            }
+            request => panic!("unexpected request {:?}", request),
+        }
+
+        let row = format!("R,{},a1,2.5,2022-05-10T13:00:00Z,[{{]", NODE);
+        assert!(reader
+            .parse(&row)
+            .unwrap_err()
+            .starts_with("invalid payment_events"));
+    }
+}
@@ -0,0 +1,17 @@
+#![forbid(unsafe_code)]
+
+pub mod analysis;
+pub mod capture;
+pub mod config;
+pub mod dao;
+pub mod events;
+pub mod export;
+pub mod import;
+pub mod logging;
+pub mod rest;
+pub mod retention;
+mod score_cache;
+pub mod scoring;
+pub mod tls;
+pub mod trust;
+pub mod webhooks;
@@ -0,0 +1,20 @@
+//! Process wide logging, `tracing` events and `log` records end up in the same output.
+
+use crate::config::{LogFormat, LoggingConfig};
+use tracing_subscriber::EnvFilter;
+
+pub fn init(config: &LoggingConfig) -> anyhow::Result<()> {
+    let filter = match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(directives) => EnvFilter::try_new(directives)?,
+        Err(_) => EnvFilter::try_new(&config.level)?,
+    };
+    // Standard output is reserved for command output, e.g. `export`.
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match config.format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    }
+    .map_err(|e| anyhow::anyhow!("failed to initialize logging: {}", e))
+}
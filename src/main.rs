@@ -1,31 +1,336 @@
 #![forbid(unsafe_code)]
 
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
-use actix_web::{App, HttpServer};
+use actix_web::middleware::Condition;
+use actix_web::{web, App, HttpServer};
 use actix_web_static_files::ResourceFiles;
-use tracing::Level;
+use chrono::{DateTime, Utc};
+use futures::prelude::*;
+use structopt::StructOpt;
 use tracing_actix_web::TracingLogger;
-use tracing_subscriber::FmtSubscriber;
 
-mod config;
-mod dao;
-mod rest;
+use reputation_aggregator_mock::dao::MigrationState;
+use reputation_aggregator_mock::events::EventHub;
+use reputation_aggregator_mock::export::{self, Dataset, ExportFormat};
+use reputation_aggregator_mock::import::{self, ImportFormat};
+use reputation_aggregator_mock::{config, dao, logging, rest, retention, scoring, tls, webhooks};
 
 include!(concat!(env!("OUT_DIR"), "/generated.rs"));
 
+#[derive(Debug, StructOpt)]
+struct Args {
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Runs the server, the default
+    Serve,
+    /// Applies or lists database migrations
+    Migrate {
+        #[structopt(subcommand)]
+        command: MigrateCommand,
+    },
+    /// Runs the sybil analysis and trust scoring, and recalculates all scores
+    RefreshScores,
+    /// Installs the scoring schema from `calc.sql`, replacing the previous one
+    InstallScoring,
+    /// Loads agreement reports from JSONL capture or CSV
+    Import(ImportArgs),
+    /// Writes agreements or node scores as CSV or Parquet
+    Export(ExportArgs),
+    /// Validates the configuration and checks the database
+    CheckConfig,
+}
+
+#[derive(Debug, StructOpt)]
+enum MigrateCommand {
+    /// Applies pending migrations
+    Up,
+    /// Lists migrations and whether they are applied
+    Status,
+}
+
+#[derive(Debug, StructOpt)]
+struct ExportArgs {
+    #[structopt(possible_values = &["agreements", "scores"])]
+    dataset: Dataset,
+    #[structopt(long, default_value = "csv", possible_values = &["csv", "parquet"])]
+    format: ExportFormat,
+    #[structopt(long, parse(try_from_str = parse_role))]
+    /// Only reports of this side, `provider` or `requestor`
+    role: Option<&'static str>,
+    #[structopt(long)]
+    /// Only agreements created at or after this RFC 3339 time
+    from: Option<DateTime<Utc>>,
+    #[structopt(long)]
+    /// Only agreements created before this RFC 3339 time
+    to: Option<DateTime<Utc>>,
+    #[structopt(long)]
+    /// Only agreements paid on this platform, e.g. `erc20-polygon-glm`
+    payment_platform: Option<String>,
+    #[structopt(long, short)]
+    /// Output file, standard output if not given
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ImportArgs {
+    /// JSONL capture (see `capture-replay`) or CSV in the layout of the agreements export
+    input: PathBuf,
+    #[structopt(long, possible_values = &["jsonl", "csv"])]
+    /// Input format, guessed from the file extension if not given
+    format: Option<ImportFormat>,
+    #[structopt(long)]
+    /// File receiving rejected input, `<input>.rejects.jsonl` if not given
+    rejects: Option<PathBuf>,
+    #[structopt(long, default_value = "500")]
+    /// Input lines stored in one transaction
+    batch_size: usize,
+    #[structopt(long)]
+    /// Do not recalculate scores after the import
+    no_refresh: bool,
+}
+
+fn parse_role(role: &str) -> Result<&'static str, String> {
+    match role {
+        "provider" => Ok("P"),
+        "requestor" => Ok("R"),
+        _ => Err(format!("unknown role: {}", role)),
+    }
+}
+
+async fn run_export(
+    config: &config::ReputationServerConfig,
+    args: ExportArgs,
+) -> anyhow::Result<()> {
+    let filter = dao::ExportFilter {
+        role_id: args.role,
+        from: args.from,
+        to: args.to,
+        payment_platform: args.payment_platform,
+    };
+    export::validate(args.dataset, args.format, &filter).map_err(anyhow::Error::msg)?;
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    let dao = connect(config).await?;
+    let mut chunks = Box::pin(export::export(dao, args.dataset, args.format, filter));
+    while let Some(chunk) = chunks.try_next().await? {
+        output.write_all(&chunk)?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+async fn run_import(
+    config: &config::ReputationServerConfig,
+    args: ImportArgs,
+) -> anyhow::Result<()> {
+    let format = match args.format {
+        Some(format) => format,
+        None => ImportFormat::from_path(&args.input)
+            .ok_or_else(|| anyhow::anyhow!("unknown input format, use --format"))?,
+    };
+    let rejects_path = args.rejects.unwrap_or_else(|| {
+        let mut path = args.input.clone().into_os_string();
+        path.push(".rejects.jsonl");
+        path.into()
+    });
+    let input = BufReader::new(File::open(&args.input)?);
+    let mut rejects = io::BufWriter::new(File::create(&rejects_path)?);
+    let dao = connect(config).await?;
+    let source = args.input.display().to_string();
+    let summary = import::import(
+        &dao,
+        &source,
+        input,
+        format,
+        &mut rejects,
+        args.batch_size.max(1),
+    )
+    .await?;
+    println!(
+        "imported {} details, {} statuses and {} payment events from {} inputs",
+        summary.rows.details, summary.rows.statuses, summary.rows.payment_events, summary.inputs
+    );
+    if summary.rejected > 0 {
+        println!(
+            "{} inputs rejected, see {}",
+            summary.rejected,
+            rejects_path.display()
+        );
+    }
+    if !args.no_refresh {
+        scoring::refresh(&dao, &EventHub::default()).await?;
+    }
+    Ok(())
+}
+
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
     let _ = dotenv::dotenv().unwrap_or_default();
-    env_logger::init();
+    let args = Args::from_args();
     let config = Arc::new(config::ReputationServerConfig::load()?);
-    let subscriber = FmtSubscriber::builder()
-        // all spans/events with a level higher than TRACE (e.g, debug, info, warn, etc.)
-        // will be written to stdout.
-        .with_max_level(Level::TRACE)
-        // completes the builder.
-        .finish();
+    logging::init(&config.logging)?;
+    match args.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config).await,
+        Command::Migrate { command } => migrate(&config, command).await,
+        Command::RefreshScores => {
+            let dao = connect(&config).await?;
+            scoring::refresh(&dao, &EventHub::default()).await?;
+            println!("scores refreshed");
+            Ok(())
+        }
+        Command::InstallScoring => {
+            let dao = connect(&config).await?;
+            dao.install_scoring().await?;
+            println!("scoring schema installed");
+            Ok(())
+        }
+        Command::Import(args) => run_import(&config, args).await,
+        Command::Export(args) => run_export(&config, args).await,
+        Command::CheckConfig => check_config(&config).await,
+    }
+}
 
+async fn migrate(
+    config: &config::ReputationServerConfig,
+    command: MigrateCommand,
+) -> anyhow::Result<()> {
+    match command {
+        MigrateCommand::Up => {
+            dao::apply_migrations(&config.database_url).await?;
+            println!("migrations applied");
+        }
+        MigrateCommand::Status => {
+            for migration in dao::migration_status(&config.database_url).await? {
+                let state = match migration.state {
+                    MigrationState::Pending => "pending",
+                    MigrationState::Applied => "applied",
+                    MigrationState::Modified => "applied, script changed since",
+                };
+                println!(
+                    "{} {:<20} {}",
+                    migration.version, migration.description, state
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the effective configuration, without secrets, and checks the database.
+async fn check_config(config: &config::ReputationServerConfig) -> anyhow::Result<()> {
+    println!("listen on: {}", config.listen_on);
+    println!("apply migrations on start: {}", config.apply_migrations);
+    match &config.tls {
+        Some(tls_config) => {
+            tls::check(tls_config)?;
+            println!(
+                "TLS: certificate {}, key {}",
+                tls_config.cert_path.display(),
+                tls_config.key_path.display()
+            );
+        }
+        None => println!("TLS: disabled, plain HTTP"),
+    }
+    if config.cors.allowed_origins.is_empty() {
+        println!("CORS: no cross-origin access");
+    } else {
+        println!(
+            "CORS allowed origins: {}",
+            config.cors.allowed_origins.join(", ")
+        );
+    }
+    let mut principals: Vec<_> = config.admin_tokens.keys().map(String::as_str).collect();
+    principals.sort_unstable();
+    if principals.is_empty() {
+        println!("admin principals: none, admin API disabled");
+    } else {
+        println!("admin principals: {}", principals.join(", "));
+    }
+    match config.score_refresh_interval {
+        Some(interval) => println!("score refresh: every {}s", interval),
+        None => println!("score refresh: disabled"),
+    }
+    let min_evidence = config.min_evidence();
+    println!(
+        "minimum evidence: {} agreements, {} counterparties",
+        min_evidence.agreements, min_evidence.counterparties
+    );
+    println!(
+        "score cache: {} entries, {}s maximum age",
+        config.score_cache.max_entries, config.score_cache.max_age
+    );
+    match &config.retention {
+        Some(retention) => println!(
+            "retention: {} days, archived to {}, every {}s",
+            retention.max_age_days,
+            retention.archive_dir.display(),
+            retention.interval
+        ),
+        None => println!("retention: disabled"),
+    }
+
+    let migrations = dao::migration_status(&config.database_url).await?;
+    let pending = migrations
+        .iter()
+        .filter(|migration| migration.state != MigrationState::Applied)
+        .count();
+    let dao = connect(config).await?;
+    let scoring_installed = dao.scoring_installed().await?;
+    println!(
+        "database: reachable, {} of {} migrations not applied, scoring schema {}",
+        pending,
+        migrations.len(),
+        if scoring_installed {
+            "installed"
+        } else {
+            "missing"
+        }
+    );
+    if let Some(replica) = &config.read_replica {
+        let lag = dao
+            .with_replica(&replica.database_url, &config.pool)?
+            .check_replica(Duration::from_secs(replica.max_lag))
+            .await
+            .map_err(|e| anyhow::anyhow!("read replica unreachable: {}", e))?;
+        match lag {
+            Some(lag) => println!(
+                "read replica: reachable, {:.1}s behind, maximum {}s",
+                lag, replica.max_lag
+            ),
+            None => println!("read replica: reachable, lag unknown"),
+        }
+    } else {
+        println!("read replica: none");
+    }
+    if pending > 0 {
+        anyhow::bail!("database is not up to date, run `migrate up`");
+    }
+    if !scoring_installed {
+        anyhow::bail!("scoring schema is missing, run `install-scoring`");
+    }
+    Ok(())
+}
+
+async fn connect(config: &config::ReputationServerConfig) -> sqlx::Result<dao::StatusDao> {
+    Ok(
+        dao::StatusDao::connect_with(&config.database_url, &config.pool)
+            .await?
+            .with_min_evidence(config.min_evidence()),
+    )
+}
+
+async fn serve(config: Arc<config::ReputationServerConfig>) -> anyhow::Result<()> {
     let bind_addr = config.listen_on;
 
     if config.apply_migrations {
@@ -35,19 +340,56 @@ async fn main() -> anyhow::Result<()> {
         log::info!("skip db migrations");
     }
 
-    HttpServer::new(move || {
-        let config = config.clone();
+    let dao = connect(&config)
+        .await?
+        .with_score_cache(&config.score_cache);
+    let dao = match &config.read_replica {
+        Some(replica) => {
+            let dao = dao.with_replica(&replica.database_url, &config.pool)?;
+            dao::spawn_replica_monitor(dao.clone(), replica.clone());
+            dao
+        }
+        None => dao,
+    };
+    let events = web::Data::new(EventHub::default());
+    if let Some(interval) = config.score_refresh_interval {
+        scoring::spawn_refresher(dao.clone(), events.clone(), Duration::from_secs(interval));
+    }
+    if let Some(retention) = &config.retention {
+        retention::spawn(dao.clone(), events.clone(), retention.clone());
+    }
+    webhooks::spawn_delivery_worker(dao.clone(), &events);
+
+    let tls_config = config.tls.as_ref().map(tls::server_config).transpose()?;
+    let data = web::Data::new(dao.clone());
+    let shutdown_timeout = config.shutdown_timeout;
+    let server = HttpServer::new(move || {
         let generated = generate();
 
         App::new()
+            .wrap(Condition::new(
+                !config.cors.allowed_origins.is_empty(),
+                rest::cors(&config.cors),
+            ))
             .wrap(TracingLogger::default())
-            .data_factory(move || dao::StatusDao::connect(config.database_url.clone()))
+            .app_data(events.clone())
+            .app_data(web::Data::from(config.clone()))
+            .app_data(data.clone())
             .configure(rest::configure)
             .service(ResourceFiles::new("/", generated))
     })
-    .bind(bind_addr)?
-    .run()
-    .await?;
+    .shutdown_timeout(shutdown_timeout);
+    let server = match tls_config {
+        Some(tls_config) => {
+            log::info!("serving HTTPS on {}", bind_addr);
+            server.bind_rustls(bind_addr, tls_config)?
+        }
+        None => server.bind(bind_addr)?,
+    };
+    server.run().await?;
 
+    // Workers are stopped, wait for queries of background tasks before leaving.
+    log::info!("server stopped, closing database pool");
+    dao.close().await;
     Ok(())
 }
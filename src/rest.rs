@@ -1,17 +1,133 @@
-use actix_web::web::ServiceConfig;
+use crate::config::CorsConfig;
+use actix_cors::Cors;
+use actix_web::get;
+use actix_web::http::{header, Method};
+use actix_web::guard::{self, GuardContext};
+use actix_web::middleware::DefaultHeaders;
+use actix_web::web::{self, ServiceConfig};
+use reputation_aggregator_model::{ApiVersion, ApiVersions};
 use serde::Deserialize;
 
+mod audit;
+mod auth;
+mod erasure;
+mod error;
+mod events;
+mod export;
+mod moderation;
+mod openapi;
+mod profile;
 mod report;
 mod score;
+mod suspicious;
+mod webhooks;
 
-#[derive(Deserialize)]
+/// Upper bound of `limit` in list endpoints.
+const MAX_LIST_LIMIT: usize = 1000;
+
+/// First path segments of endpoints that used to be served at the server root.
+const LEGACY_ROOTS: &[&str] = &[
+    "provider",
+    "requestor",
+    "standard_score",
+    "events",
+    "admin",
+    "openapi.json",
+    "openapi",
+];
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 struct ListQuery {
+    /// Number of items to skip
     start: Option<u64>,
+    /// Maximum number of items to return, 100 by default and at most 1000
     limit: Option<usize>,
 }
 
+impl ListQuery {
+    fn start(&self) -> i64 {
+        self.start.unwrap_or_default().min(i64::MAX as u64) as i64
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(100).min(MAX_LIST_LIMIT) as i64
+    }
+}
+
 pub fn configure(config: &mut ServiceConfig) {
     config
+        .configure(error::configure)
+        .service(versions)
+        .service(web::scope(ApiVersion::V1.prefix()).configure(configure_v1))
+        // Deprecated aliases, guarded so that everything else falls through to the static UI.
+        .service(
+            web::scope("")
+                .guard(guard::fn_guard(is_legacy_path))
+                .wrap(
+                    DefaultHeaders::new()
+                        .add(("Deprecation", "true"))
+                        .add(("Link", "</api/v1>; rel=\"successor-version\"")),
+                )
+                .configure(configure_v1),
+        );
+}
+
+/// CORS policy for browser dashboards on other origins.
+///
+/// Apply it only when origins are configured, without any it refuses requests carrying an
+/// `Origin` header, same-origin ones included.
+pub fn cors(config: &CorsConfig) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allowed_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::ACCEPT,
+            header::IF_NONE_MATCH,
+        ])
+        .expose_headers([header::CONTENT_DISPOSITION, header::ETAG])
+        .max_age(config.max_age);
+    for origin in &config.allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    cors
+}
+
+fn configure_v1(config: &mut ServiceConfig) {
+    config
+        .configure(openapi::configure)
+        .configure(events::configure)
+        .configure(webhooks::configure)
+        .configure(suspicious::configure)
+        .configure(erasure::configure)
+        .configure(export::configure)
+        .configure(moderation::configure)
+        .configure(audit::configure)
         .configure(report::configure)
-        .configure(score::configure);
+        .configure(score::configure)
+        // `/{role_id}/{node_id}` shadows every other two segment path, keep it last.
+        .configure(profile::configure);
+}
+
+fn is_legacy_path(ctx: &GuardContext) -> bool {
+    let path = ctx.head().uri.path();
+    let root = path
+        .trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    LEGACY_ROOTS.contains(&root)
+}
+
+#[get("/api")]
+async fn versions() -> web::Json<ApiVersions> {
+    web::Json(ApiVersions {
+        versions: vec!["v1".to_string()],
+        deprecated: vec!["legacy".to_string()],
+    })
 }
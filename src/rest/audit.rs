@@ -0,0 +1,106 @@
+use crate::dao::{self, AuditEntry, AuditFilter, NewAuditEntry};
+use crate::rest::auth::Admin;
+use crate::rest::error::ApiError;
+use crate::rest::ListQuery;
+use actix_web::dev::Payload;
+use actix_web::{get, web, FromRequest, HttpMessage, HttpRequest};
+use futures::future::{ready, Ready};
+use serde::{Deserialize, Serialize};
+use tracing_actix_web::RequestId;
+
+pub fn configure(config: &mut web::ServiceConfig) {
+    config.service(list_audit);
+}
+
+/// Origin of a write request, recorded in the audit log.
+pub struct Audit {
+    source_ip: Option<String>,
+    request_id: Option<String>,
+}
+
+impl FromRequest for Audit {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        // Peer address rather than forwarding headers, these are set by the client.
+        ready(Ok(Audit {
+            source_ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+            request_id: req.extensions().get::<RequestId>().map(ToString::to_string),
+        }))
+    }
+}
+
+impl Audit {
+    /// Audit log entry of a write, to be stored by the DAO together with it. `payload` is
+    /// hashed as serialized JSON.
+    pub fn entry<'a, T: Serialize>(
+        &'a self,
+        admin: Option<&'a Admin>,
+        operation: &'a str,
+        target: &'a str,
+        payload: &T,
+    ) -> Result<NewAuditEntry<'a>, ApiError> {
+        self.entry_without_payload(admin, operation, target)
+            .with_payload(payload)
+            .map_err(|e| ApiError::Internal(e.to_string()))
+    }
+
+    /// Audit log entry of a write of a request without a body.
+    pub fn entry_without_payload<'a>(
+        &'a self,
+        admin: Option<&'a Admin>,
+        operation: &'a str,
+        target: &'a str,
+    ) -> NewAuditEntry<'a> {
+        NewAuditEntry {
+            operation,
+            target,
+            principal: admin.map(|admin| admin.principal.as_str()),
+            source_ip: self.source_ip.as_deref(),
+            request_id: self.request_id.as_deref(),
+            payload_sha256: None,
+        }
+    }
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct AuditQuery {
+    /// Only entries of this operation, e.g. `saveAgreementStatus`
+    operation: Option<String>,
+    /// Only entries of this admin principal
+    principal: Option<String>,
+    /// Only entries with target starting with this prefix, e.g. `P/<node id>`
+    target: Option<String>,
+}
+
+/// Lists audit log entries of write operations, newest first.
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    tag = "admin",
+    params(ListQuery, AuditQuery),
+    responses(
+        (status = 200, description = "Audit log entries", body = [AuditEntry]),
+        (status = 401, description = "Missing or invalid admin token")
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/admin/audit")]
+async fn list_audit(
+    _admin: Admin,
+    list: web::Query<ListQuery>,
+    query: web::Query<AuditQuery>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<Vec<AuditEntry>>, ApiError> {
+    let filter = AuditFilter {
+        operation: query.operation.as_deref(),
+        principal: query.principal.as_deref(),
+        target: query.target.as_deref(),
+    };
+    let entries = data
+        .list_audit_entries(&filter, list.start(), list.limit())
+        .await?;
+    Ok(web::Json(entries))
+}
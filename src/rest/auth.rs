@@ -0,0 +1,51 @@
+use crate::config::ReputationServerConfig;
+use crate::rest::error::ApiError;
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{web, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+
+/// Operator authenticated with one of the configured `admin_tokens`.
+pub struct Admin {
+    pub principal: String,
+}
+
+/// Compares without short-circuiting on the first differing byte.
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a
+            .bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn authenticate(req: &HttpRequest) -> Result<Admin, ApiError> {
+    let config = req
+        .app_data::<web::Data<ReputationServerConfig>>()
+        .ok_or_else(|| ApiError::Internal("server config not available".to_string()))?;
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(ApiError::Unauthorized("missing bearer token"))?;
+
+    config
+        .admin_tokens
+        .iter()
+        .find(|(_, admin_token)| same_token(admin_token, token))
+        .map(|(principal, _)| Admin {
+            principal: principal.clone(),
+        })
+        .ok_or(ApiError::Unauthorized("invalid token"))
+}
+
+impl FromRequest for Admin {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(authenticate(req))
+    }
+}
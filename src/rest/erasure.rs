@@ -0,0 +1,72 @@
+use crate::config::ReputationServerConfig;
+use crate::dao::{self, ErasedNode};
+use crate::events::EventHub;
+use crate::rest::audit::Audit;
+use crate::rest::auth::Admin;
+use crate::rest::error::ApiError;
+use crate::{retention, scoring};
+use actix_web::{delete, web};
+use reputation_aggregator_model::NodeId;
+
+pub fn configure(config: &mut web::ServiceConfig) {
+    config.service(erase_node);
+}
+
+/// Erases all data about the node and recalculates scores without it.
+///
+/// Agreements the node was reported as counterparty of are removed as well, and so are
+/// moderation records, webhooks filtering on the node and deliveries naming it. Retention
+/// archives are rewritten without any report of those agreements.
+///
+/// The append-only audit log is the one exception: its entries are kept as the record of
+/// who changed what, including this erasure. Their targets still name the node and its
+/// agreements, payloads are only stored as SHA-256 hashes.
+#[utoipa::path(
+    delete,
+    path = "/admin/node/{node_id}",
+    tag = "admin",
+    params(("node_id" = String, Path, description = "Erased node id")),
+    responses(
+        (status = 200, description = "Removed rows", body = ErasedNode),
+        (status = 401, description = "Missing or invalid admin token"),
+        (status = 404, description = "Nothing is stored about the node")
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/admin/node/{node_id}")]
+async fn erase_node(
+    admin: Admin,
+    audit: Audit,
+    path: web::Path<(NodeId,)>,
+    data: web::Data<dao::StatusDao>,
+    events: web::Data<EventHub>,
+    config: web::Data<ReputationServerConfig>,
+) -> Result<web::Json<ErasedNode>, ApiError> {
+    let node_id = path.into_inner().0.to_string();
+    let mut erased = data
+        .erase_node(
+            &node_id,
+            &audit.entry_without_payload(Some(&admin), "eraseNode", &node_id),
+        )
+        .await?;
+    // Runs after the reports are deleted, so retention cannot archive them again.
+    if let Some(retention) = &config.retention {
+        let dir = retention.archive_dir.clone();
+        let id = node_id.clone();
+        erased.archived_reports = web::block(move || retention::erase(&dir, &id))
+            .await
+            .map_err(|e| ApiError::Internal(e.to_string()))?
+            .map_err(|e| ApiError::Internal(format!("failed to erase archives: {}", e)))?;
+    }
+    if erased.is_empty() {
+        return Err(ApiError::NotFound(format!("node {} not found", node_id)));
+    }
+    log::info!(
+        "node {} erased by {}, {} agreements removed",
+        node_id,
+        admin.principal,
+        erased.agreements
+    );
+    scoring::refresh(&data, &events).await?;
+    Ok(web::Json(erased))
+}
@@ -0,0 +1,114 @@
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use reputation_aggregator_model::{ErrorCode, ErrorResponse};
+use thiserror::Error;
+
+/// Postgres `unique_violation`.
+const UNIQUE_VIOLATION: &str = "23505";
+
+/// Error returned by REST handlers, rendered as [`ErrorResponse`].
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("invalid path: {0}")]
+    InvalidPath(String),
+    #[error("invalid query: {0}")]
+    InvalidQuery(String),
+    #[error("malformed body: {0}")]
+    MalformedBody(String),
+    #[error("invalid body: {0}")]
+    InvalidBody(String),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Unauthorized(&'static str),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("database error: {0}")]
+    Database(sqlx::Error),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::InvalidPath(_) => ErrorCode::InvalidPath,
+            Self::InvalidQuery(_) => ErrorCode::InvalidQuery,
+            Self::MalformedBody(_) => ErrorCode::MalformedBody,
+            Self::InvalidBody(_) => ErrorCode::InvalidBody,
+            Self::BadRequest(_) => ErrorCode::BadRequest,
+            Self::Unauthorized(_) => ErrorCode::Unauthorized,
+            Self::NotFound(_) => ErrorCode::NotFound,
+            Self::Conflict(_) => ErrorCode::Conflict,
+            Self::Database(_) | Self::Internal(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        match &e {
+            sqlx::Error::Database(db_error)
+                if db_error.code().as_deref() == Some(UNIQUE_VIOLATION) =>
+            {
+                ApiError::Conflict("already reported".to_string())
+            }
+            _ => ApiError::Database(e),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self.code() {
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::InvalidBody => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Internal | ErrorCode::Unknown => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // Internal details end up in the log, never in the response.
+        let message = match self {
+            Self::Database(_) | Self::Internal(_) => {
+                log::error!("{}", self);
+                "internal server error".to_string()
+            }
+            _ => self.to_string(),
+        };
+        HttpResponse::build(self.status_code()).json(ErrorResponse {
+            code: self.code(),
+            message,
+        })
+    }
+}
+
+/// Extractor configs rendering rejected requests as [`ApiError`].
+pub fn configure(config: &mut web::ServiceConfig) {
+    config
+        .app_data(web::PathConfig::default().error_handler(path_error))
+        .app_data(web::QueryConfig::default().error_handler(query_error))
+        .app_data(web::JsonConfig::default().error_handler(json_error));
+}
+
+fn path_error(e: PathError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::InvalidPath(e.to_string()).into()
+}
+
+fn query_error(e: QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::InvalidQuery(e.to_string()).into()
+}
+
+fn json_error(e: JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    match e {
+        JsonPayloadError::Deserialize(e) if e.is_data() => ApiError::InvalidBody(e.to_string()),
+        e => ApiError::MalformedBody(e.to_string()),
+    }
+    .into()
+}
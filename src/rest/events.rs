@@ -0,0 +1,80 @@
+use crate::events::EventHub;
+use actix_web::web::{self, Bytes};
+use actix_web::{get, HttpResponse};
+use futures::prelude::*;
+use reputation_aggregator_model::{AgreementRole, NodeId};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+pub fn configure(config: &mut web::ServiceConfig) {
+    config.service(events);
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub(super) struct EventsQuery {
+    /// Only events reported by or scoring this side
+    role: Option<AgreementRole>,
+    /// Only events of this node
+    #[param(value_type = Option<String>)]
+    node_id: Option<NodeId>,
+}
+
+/// Server-Sent Events stream of ingested reports and score changes.
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "events",
+    params(EventsQuery),
+    responses((
+        status = 200,
+        description = "`text/event-stream` of events, event name is the event type",
+        body = ReputationEvent,
+        content_type = "text/event-stream"
+    ))
+)]
+#[get("/events")]
+async fn events(query: web::Query<EventsQuery>, hub: web::Data<EventHub>) -> HttpResponse {
+    let EventsQuery { role, node_id } = query.into_inner();
+    let frames = stream::unfold(hub.subscribe(), move |mut receiver| async move {
+        loop {
+            let frame = match tokio::time::timeout(KEEP_ALIVE_INTERVAL, receiver.recv()).await {
+                Err(_elapsed) => Bytes::from_static(b": keep-alive\n\n"),
+                Ok(Ok(event)) => {
+                    if role.is_some_and(|role| role != event.role())
+                        || node_id.is_some_and(|node_id| node_id != event.node_id())
+                    {
+                        continue;
+                    }
+                    match serde_json::to_string(&event) {
+                        Ok(data) => Bytes::from(format!(
+                            "event: {}\ndata: {}\n\n",
+                            event.event_type(),
+                            data
+                        )),
+                        Err(e) => {
+                            log::error!("failed to serialize event: {}", e);
+                            continue;
+                        }
+                    }
+                }
+                Ok(Err(RecvError::Lagged(skipped))) => {
+                    log::warn!("event subscriber lagged, {} events dropped", skipped);
+                    continue;
+                }
+                Ok(Err(RecvError::Closed)) => return None,
+            };
+            return Some((Ok::<_, Infallible>(frame), receiver));
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(frames)
+}
@@ -0,0 +1,105 @@
+use crate::dao::{self, role_as_db, ExportFilter};
+use crate::export::{self, Dataset, ExportFormat};
+use crate::rest::auth::Admin;
+use crate::rest::error::ApiError;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{get, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use reputation_aggregator_model::AgreementRole;
+use serde::Deserialize;
+
+pub fn configure(config: &mut web::ServiceConfig) {
+    config.service(export_agreements).service(export_scores);
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub(super) struct ExportQuery {
+    /// Output format, `csv` by default
+    #[param(inline)]
+    format: Option<ExportFormat>,
+    /// Only reports of this side
+    role: Option<AgreementRole>,
+    /// Only agreements created at or after this time
+    from: Option<DateTime<Utc>>,
+    /// Only agreements created before this time
+    to: Option<DateTime<Utc>>,
+    /// Only agreements paid on this platform, e.g. `erc20-polygon-glm`
+    payment_platform: Option<String>,
+}
+
+fn stream_export(
+    dao: &dao::StatusDao,
+    dataset: Dataset,
+    query: ExportQuery,
+) -> Result<HttpResponse, ApiError> {
+    let format = query.format.unwrap_or(ExportFormat::Csv);
+    let filter = ExportFilter {
+        role_id: query.role.map(role_as_db),
+        from: query.from,
+        to: query.to,
+        payment_platform: query.payment_platform,
+    };
+    export::validate(dataset, format, &filter).map_err(ApiError::InvalidQuery)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "{}.{}",
+                dataset.name(),
+                format.extension()
+            ))],
+        })
+        .streaming(export::export(dao.clone(), dataset, format, filter)))
+}
+
+/// Streams agreement reports with their details and classification.
+///
+/// Amounts are exported as decimal text and timestamps in UTC.
+#[utoipa::path(
+    get,
+    path = "/admin/export/agreements",
+    tag = "admin",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "CSV with a header line or a Parquet file"),
+        (status = 400, description = "Invalid filter or unsupported format"),
+        (status = 401, description = "Missing or invalid admin token")
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/admin/export/agreements")]
+async fn export_agreements(
+    _admin: Admin,
+    query: web::Query<ExportQuery>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<HttpResponse, ApiError> {
+    stream_export(&data, Dataset::Agreements, query.into_inner())
+}
+
+/// Streams standard node scores, within the payment platform if one is given.
+///
+/// The date range does not apply to scores.
+#[utoipa::path(
+    get,
+    path = "/admin/export/scores",
+    tag = "admin",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "CSV with a header line or a Parquet file"),
+        (status = 400, description = "Invalid filter or unsupported format"),
+        (status = 401, description = "Missing or invalid admin token")
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/admin/export/scores")]
+async fn export_scores(
+    _admin: Admin,
+    query: web::Query<ExportQuery>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<HttpResponse, ApiError> {
+    stream_export(&data, Dataset::Scores, query.into_inner())
+}
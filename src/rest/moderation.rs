@@ -0,0 +1,360 @@
+use crate::dao::{
+    self, role_as_db, AuditEntry, NewNodeStatus, NewNote, NewQuarantine, NewScoreOverride, NodeNote,
+};
+use crate::rest::audit::Audit;
+use crate::rest::auth::Admin;
+use crate::rest::error::ApiError;
+use crate::rest::ListQuery;
+use actix_web::{delete, get, post, put, web};
+use reputation_aggregator_model::{AgreementRole, NodeId};
+
+pub fn configure(config: &mut web::ServiceConfig) {
+    config
+        .service(delete_report)
+        .service(quarantine_agreement)
+        .service(release_agreement)
+        .service(set_node_status)
+        .service(clear_node_status)
+        .service(add_note)
+        .service(list_notes)
+        .service(override_score)
+        .service(remove_override)
+        .service(list_actions);
+}
+
+/// Removes the report one node sent about an agreement.
+#[utoipa::path(
+    delete,
+    path = "/admin/agreement/{role_id}/{node_id}/{agreement_id}",
+    tag = "admin",
+    params(
+        ("role_id" = AgreementRole, Path, description = "Side of the reporting node"),
+        ("node_id" = String, Path, description = "Reporting node id"),
+        ("agreement_id" = String, Path, description = "Agreement id")
+    ),
+    responses(
+        (status = 200, description = "Report removed"),
+        (status = 401, description = "Missing or invalid admin token"),
+        (status = 404, description = "Report not found")
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/admin/agreement/{role_id}/{node_id}/{agreement_id}")]
+async fn delete_report(
+    admin: Admin,
+    audit: Audit,
+    path: web::Path<(AgreementRole, NodeId, String)>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<()>, ApiError> {
+    let (role_id, node_id, agreement_id) = path.into_inner();
+    let target = format!("{}/{}/{}", role_as_db(role_id), node_id, agreement_id);
+    if !data
+        .delete_agreement_report(
+            role_as_db(role_id),
+            &node_id.to_string(),
+            &agreement_id,
+            &audit.entry_without_payload(Some(&admin), "deleteReport", &target),
+        )
+        .await?
+    {
+        return Err(ApiError::NotFound(format!(
+            "report of agreement {} not found",
+            agreement_id
+        )));
+    }
+    Ok(web::Json(()))
+}
+
+/// Excludes the agreement from scoring from the next score refresh on.
+#[utoipa::path(
+    put,
+    path = "/admin/agreement/{agreement_id}/quarantine",
+    tag = "admin",
+    params(("agreement_id" = String, Path, description = "Agreement id")),
+    request_body = NewQuarantine,
+    responses(
+        (status = 200, description = "Agreement quarantined"),
+        (status = 401, description = "Missing or invalid admin token"),
+        (status = 404, description = "Agreement not found")
+    ),
+    security(("admin_token" = []))
+)]
+#[put("/admin/agreement/{agreement_id}/quarantine")]
+async fn quarantine_agreement(
+    admin: Admin,
+    audit: Audit,
+    path: web::Path<(String,)>,
+    body: web::Json<NewQuarantine>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<()>, ApiError> {
+    let (agreement_id,) = path.into_inner();
+    let audit_entry = audit.entry(Some(&admin), "quarantine", &agreement_id, &*body)?;
+    if !data
+        .quarantine_agreement(
+            &agreement_id,
+            body.reason.as_deref(),
+            &admin.principal,
+            &audit_entry,
+        )
+        .await?
+    {
+        return Err(ApiError::NotFound(format!(
+            "agreement {} not found",
+            agreement_id
+        )));
+    }
+    Ok(web::Json(()))
+}
+
+/// Returns a quarantined agreement to scoring.
+#[utoipa::path(
+    delete,
+    path = "/admin/agreement/{agreement_id}/quarantine",
+    tag = "admin",
+    params(("agreement_id" = String, Path, description = "Agreement id")),
+    responses(
+        (status = 200, description = "Agreement released"),
+        (status = 401, description = "Missing or invalid admin token"),
+        (status = 404, description = "Agreement is not quarantined")
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/admin/agreement/{agreement_id}/quarantine")]
+async fn release_agreement(
+    admin: Admin,
+    audit: Audit,
+    path: web::Path<(String,)>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<()>, ApiError> {
+    let (agreement_id,) = path.into_inner();
+    if !data
+        .release_agreement(
+            &agreement_id,
+            &audit.entry_without_payload(Some(&admin), "release", &agreement_id),
+        )
+        .await?
+    {
+        return Err(ApiError::NotFound(format!(
+            "agreement {} is not quarantined",
+            agreement_id
+        )));
+    }
+    Ok(web::Json(()))
+}
+
+/// Bans or whitelists the node.
+#[utoipa::path(
+    put,
+    path = "/admin/node/{node_id}/status",
+    tag = "admin",
+    params(("node_id" = String, Path, description = "Node id")),
+    request_body = NewNodeStatus,
+    responses(
+        (status = 200, description = "Status set"),
+        (status = 401, description = "Missing or invalid admin token")
+    ),
+    security(("admin_token" = []))
+)]
+#[put("/admin/node/{node_id}/status")]
+async fn set_node_status(
+    admin: Admin,
+    audit: Audit,
+    path: web::Path<(NodeId,)>,
+    body: web::Json<NewNodeStatus>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<()>, ApiError> {
+    let node_id = path.into_inner().0.to_string();
+    let audit_entry = audit.entry(Some(&admin), "setNodeStatus", &node_id, &*body)?;
+    data.set_node_status(&node_id, &body, &admin.principal, &audit_entry)
+        .await?;
+    Ok(web::Json(()))
+}
+
+/// Lifts a ban or whitelisting.
+#[utoipa::path(
+    delete,
+    path = "/admin/node/{node_id}/status",
+    tag = "admin",
+    params(("node_id" = String, Path, description = "Node id")),
+    responses(
+        (status = 200, description = "Status cleared"),
+        (status = 401, description = "Missing or invalid admin token"),
+        (status = 404, description = "Node has no status")
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/admin/node/{node_id}/status")]
+async fn clear_node_status(
+    admin: Admin,
+    audit: Audit,
+    path: web::Path<(NodeId,)>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<()>, ApiError> {
+    let node_id = path.into_inner().0.to_string();
+    if !data
+        .clear_node_status(
+            &node_id,
+            &audit.entry_without_payload(Some(&admin), "clearNodeStatus", &node_id),
+        )
+        .await?
+    {
+        return Err(ApiError::NotFound(format!(
+            "node {} has no status",
+            node_id
+        )));
+    }
+    Ok(web::Json(()))
+}
+
+/// Attaches an operator note to the node.
+#[utoipa::path(
+    post,
+    path = "/admin/node/{node_id}/notes",
+    tag = "admin",
+    params(("node_id" = String, Path, description = "Node id")),
+    request_body = NewNote,
+    responses(
+        (status = 200, description = "Stored note", body = NodeNote),
+        (status = 401, description = "Missing or invalid admin token")
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/admin/node/{node_id}/notes")]
+async fn add_note(
+    admin: Admin,
+    audit: Audit,
+    path: web::Path<(NodeId,)>,
+    body: web::Json<NewNote>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<NodeNote>, ApiError> {
+    let node_id = path.into_inner().0.to_string();
+    let audit_entry = audit.entry(Some(&admin), "addNote", &node_id, &*body)?;
+    let note = data
+        .insert_node_note(&node_id, &body.note, &admin.principal, &audit_entry)
+        .await?;
+    Ok(web::Json(note))
+}
+
+/// Lists operator notes of the node, oldest first.
+#[utoipa::path(
+    get,
+    path = "/admin/node/{node_id}/notes",
+    tag = "admin",
+    params(("node_id" = String, Path, description = "Node id")),
+    responses(
+        (status = 200, description = "Notes", body = [NodeNote]),
+        (status = 401, description = "Missing or invalid admin token")
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/admin/node/{node_id}/notes")]
+async fn list_notes(
+    _admin: Admin,
+    path: web::Path<(NodeId,)>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<Vec<NodeNote>>, ApiError> {
+    let (node_id,) = path.into_inner();
+    Ok(web::Json(data.list_node_notes(&node_id.to_string()).await?))
+}
+
+/// Replaces the overall standard score of the node from the next score refresh on.
+#[utoipa::path(
+    put,
+    path = "/admin/override/{role_id}/{node_id}",
+    tag = "admin",
+    params(
+        ("role_id" = AgreementRole, Path, description = "Scored side"),
+        ("node_id" = String, Path, description = "Scored node id")
+    ),
+    request_body = NewScoreOverride,
+    responses(
+        (status = 200, description = "Score overridden"),
+        (status = 401, description = "Missing or invalid admin token")
+    ),
+    security(("admin_token" = []))
+)]
+#[put("/admin/override/{role_id}/{node_id}")]
+async fn override_score(
+    admin: Admin,
+    audit: Audit,
+    path: web::Path<(AgreementRole, NodeId)>,
+    body: web::Json<NewScoreOverride>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<()>, ApiError> {
+    let (role_id, node_id) = path.into_inner();
+    let target = format!("{}/{}", role_as_db(role_id), node_id);
+    data.set_score_override(
+        role_as_db(role_id),
+        &node_id.to_string(),
+        &body,
+        &admin.principal,
+        &audit.entry(Some(&admin), "overrideScore", &target, &*body)?,
+    )
+    .await?;
+    Ok(web::Json(()))
+}
+
+/// Returns the node to its computed score.
+#[utoipa::path(
+    delete,
+    path = "/admin/override/{role_id}/{node_id}",
+    tag = "admin",
+    params(
+        ("role_id" = AgreementRole, Path, description = "Scored side"),
+        ("node_id" = String, Path, description = "Scored node id")
+    ),
+    responses(
+        (status = 200, description = "Override removed"),
+        (status = 401, description = "Missing or invalid admin token"),
+        (status = 404, description = "Score is not overridden")
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/admin/override/{role_id}/{node_id}")]
+async fn remove_override(
+    admin: Admin,
+    audit: Audit,
+    path: web::Path<(AgreementRole, NodeId)>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<()>, ApiError> {
+    let (role_id, node_id) = path.into_inner();
+    let target = format!("{}/{}", role_as_db(role_id), node_id);
+    if !data
+        .delete_score_override(
+            role_as_db(role_id),
+            &node_id.to_string(),
+            &audit.entry_without_payload(Some(&admin), "removeOverride", &target),
+        )
+        .await?
+    {
+        return Err(ApiError::NotFound(format!(
+            "score of {} is not overridden",
+            node_id
+        )));
+    }
+    Ok(web::Json(()))
+}
+
+/// Lists audit log entries of the moderation endpoints above, newest first.
+#[utoipa::path(
+    get,
+    path = "/admin/moderation",
+    tag = "admin",
+    params(ListQuery),
+    responses(
+        (status = 200, description = "Audit log entries of moderation actions", body = [AuditEntry]),
+        (status = 401, description = "Missing or invalid admin token")
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/admin/moderation")]
+async fn list_actions(
+    _admin: Admin,
+    query: web::Query<ListQuery>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<Vec<AuditEntry>>, ApiError> {
+    let actions = data
+        .list_moderation_actions(query.start(), query.limit())
+        .await?;
+    Ok(web::Json(actions))
+}
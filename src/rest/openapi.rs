@@ -0,0 +1,161 @@
+use super::{
+    audit, erasure, events, export, moderation, profile, report, score, suspicious, webhooks,
+};
+use crate::analysis;
+use crate::dao;
+use actix_web::{get, web, HttpResponse};
+use reputation_aggregator_model::{
+    AgreementClassification, AgreementInfo, AgreementRole, DocumentEvent, DocumentEventType,
+    DocumentType, ErrorCode, ErrorResponse, PaymentDetails, ReportResult, ReputationEvent, Status,
+};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{Content, Ref, ResponseBuilder};
+use utoipa::{Modify, OpenApi};
+
+/// OpenAPI document of the whole REST surface.
+///
+/// The served document is compared against the committed `openapi.json` by `tests/openapi.rs`,
+/// run it with `UPDATE_OPENAPI=1` after changing any handler.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Reputation aggregator",
+        description = "Collects agreement and payment reports from Golem nodes and scores them."
+    ),
+    servers((url = "/api/v1")),
+    paths(
+        report::list_nodes,
+        report::list_agreements,
+        report::get_agreement_details,
+        report::save_agreement_details,
+        report::save_agreement_status,
+        profile::node_profile,
+        score::standard_score,
+        score::ranking,
+        events::events,
+        webhooks::register_webhook,
+        webhooks::list_webhooks,
+        webhooks::delete_webhook,
+        webhooks::list_deliveries,
+        suspicious::suspicious,
+        erasure::erase_node,
+        export::export_agreements,
+        export::export_scores,
+        moderation::delete_report,
+        moderation::quarantine_agreement,
+        moderation::release_agreement,
+        moderation::set_node_status,
+        moderation::clear_node_status,
+        moderation::add_note,
+        moderation::list_notes,
+        moderation::override_score,
+        moderation::remove_override,
+        moderation::list_actions,
+        audit::list_audit,
+    ),
+    components(schemas(
+        AgreementRole,
+        AgreementInfo,
+        Status,
+        PaymentDetails,
+        DocumentEvent,
+        DocumentType,
+        DocumentEventType,
+        ReportResult,
+        AgreementClassification,
+        ReputationEvent,
+        ErrorCode,
+        ErrorResponse,
+        dao::Agreement,
+        dao::NodeProfile,
+        dao::StandardScore,
+        dao::ScoreStatus,
+        dao::ScoringAlgorithm,
+        dao::ConfidenceInterval,
+        dao::RankedNode,
+        dao::Webhook,
+        dao::NewWebhook,
+        dao::WebhookDelivery,
+        dao::ErasedNode,
+        dao::NodeStatus,
+        dao::NewNodeStatus,
+        dao::NewQuarantine,
+        dao::NewNote,
+        dao::NodeNote,
+        dao::NewScoreOverride,
+        dao::AuditEntry,
+        webhooks::RegisteredWebhook,
+        analysis::Finding,
+        analysis::FindingKind,
+    )),
+    modifiers(&AdminSecurity, &ErrorResponses),
+    tags(
+        (name = "report", description = "Agreement and status reports"),
+        (name = "node", description = "Node statistics"),
+        (name = "score", description = "Node scores"),
+        (name = "events", description = "Live event stream"),
+        (name = "admin", description = "Administration, requires an admin token"),
+    )
+)]
+pub struct ApiDoc;
+
+struct AdminSecurity;
+
+impl Modify for AdminSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "admin_token",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
+/// Documents the [`ErrorResponse`] body on every error response and adds a default one.
+struct ErrorResponses;
+
+impl Modify for ErrorResponses {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let content = || Content::new(Ref::from_schema_name("ErrorResponse"));
+        for operation in openapi
+            .paths
+            .paths
+            .values_mut()
+            .flat_map(|item| item.operations.values_mut())
+        {
+            let responses = &mut operation.responses.responses;
+            for (status, response) in responses.iter_mut() {
+                if let utoipa::openapi::RefOr::T(response) = response {
+                    if !status.starts_with('2') && response.content.is_empty() {
+                        response.content.insert("application/json".into(), content());
+                    }
+                }
+            }
+            responses.insert(
+                "default".into(),
+                ResponseBuilder::new()
+                    .description("Error")
+                    .content("application/json", content())
+                    .build()
+                    .into(),
+            );
+        }
+    }
+}
+
+pub fn configure(config: &mut web::ServiceConfig) {
+    config.service(openapi_json).service(openapi_viewer);
+}
+
+#[get("/openapi.json")]
+async fn openapi_json() -> web::Json<utoipa::openapi::OpenApi> {
+    web::Json(ApiDoc::openapi())
+}
+
+#[get("/openapi")]
+async fn openapi_viewer() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(include_str!("openapi.html"))
+}
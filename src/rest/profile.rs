@@ -0,0 +1,34 @@
+use crate::dao;
+use crate::rest::error::ApiError;
+use actix_web::{get, web};
+use reputation_aggregator_model::{AgreementRole, NodeId};
+
+pub fn configure(config: &mut web::ServiceConfig) {
+    config.service(node_profile);
+}
+
+/// Returns aggregated statistics of the node in given role.
+#[utoipa::path(
+    get,
+    path = "/{role_id}/{node_id}",
+    tag = "node",
+    params(
+        ("role_id" = AgreementRole, Path, description = "Node side"),
+        ("node_id" = String, Path, description = "Node id")
+    ),
+    responses(
+        (status = 200, description = "Node profile", body = NodeProfile),
+        (status = 404, description = "Node did not report any agreement in the role")
+    )
+)]
+#[get("/{role_id}/{node_id}")]
+async fn node_profile(
+    path: web::Path<(AgreementRole, NodeId)>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<dao::NodeProfile>, ApiError> {
+    let (role, node_id) = path.into_inner();
+    data.node_profile(role, &node_id.to_string())
+        .await?
+        .map(web::Json)
+        .ok_or_else(|| ApiError::NotFound(format!("node {} not found", node_id)))
+}
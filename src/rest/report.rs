@@ -1,8 +1,13 @@
-use crate::dao;
+use crate::dao::{self, role_as_db, Agreement};
+use crate::events::EventHub;
+use crate::rest::audit::Audit;
+use crate::rest::error::ApiError;
 use actix_web::web;
-use actix_web::Result;
 use actix_web::{get, post};
-use reputation_aggregator_model::{AgreementInfo, NodeId, ReportResult, Status};
+use reputation_aggregator_model::{
+    AgreementClassification, AgreementInfo, AgreementRole, NodeId, ReportResult, ReputationEvent,
+    Status,
+};
 use serde::Deserialize;
 
 pub fn configure(config: &mut web::ServiceConfig) {
@@ -10,93 +15,182 @@ pub fn configure(config: &mut web::ServiceConfig) {
         .service(list_nodes)
         .service(list_agreements)
         .service(get_agreement_details)
-        .service(save_agreement_details);
-}
-
-#[derive(Deserialize)]
-#[serde(rename_all = "lowercase")]
-enum Role {
-    Provider,
-    Requestor,
-}
-
-impl Role {
-    fn as_db(&self) -> &'static str {
-        match self {
-            Self::Provider => "P",
-            Self::Requestor => "R",
-        }
-    }
+        .service(save_agreement_details)
+        .service(save_agreement_status);
 }
 
+/// Lists nodes that reported any agreement status.
+#[utoipa::path(
+    get,
+    path = "/{role_id}",
+    tag = "report",
+    params(("role_id" = AgreementRole, Path, description = "Reporting side")),
+    responses((status = 200, description = "Node ids", body = [String]))
+)]
 #[get("/{role_id}")]
 async fn list_nodes(
-    path: web::Path<(Role,)>,
+    path: web::Path<(AgreementRole,)>,
     data: web::Data<dao::StatusDao>,
-) -> Result<web::Json<Vec<String>>> {
+) -> Result<web::Json<Vec<String>>, ApiError> {
     let (role,) = path.into_inner();
 
-    Ok(web::Json(data.list(role.as_db()).await.map_err(|e| {
-        actix_web::error::ErrorInternalServerError(e)
-    })?))
+    Ok(web::Json(data.list(role_as_db(role)).await?))
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AgreementsQuery {
+    /// Only agreements with this classification
+    classification: Option<AgreementClassification>,
 }
 
+/// Lists agreements reported by the node.
+#[utoipa::path(
+    get,
+    path = "/{role_id}/{node_id}/agreement",
+    tag = "report",
+    params(
+        ("role_id" = AgreementRole, Path, description = "Reporting side"),
+        ("node_id" = String, Path, description = "Reporting node id"),
+        AgreementsQuery
+    ),
+    responses((status = 200, description = "Reported agreements", body = [Agreement]))
+)]
 #[get("/{role_id}/{node_id}/agreement")]
 async fn list_agreements(
-    path: web::Path<(Role, NodeId)>,
+    path: web::Path<(AgreementRole, NodeId)>,
+    query: web::Query<AgreementsQuery>,
     data: web::Data<dao::StatusDao>,
-) -> actix_web::Result<web::Json<Vec<dao::Agreement>>> {
+) -> Result<web::Json<Vec<Agreement>>, ApiError> {
     let (role, node_id) = path.into_inner();
-    let agreements = data
-        .list_agreements(role.as_db(), &node_id.to_string())
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    let mut agreements = data
+        .list_agreements(role_as_db(role), &node_id.to_string())
+        .await?;
+    if let Some(classification) = query.classification {
+        agreements.retain(|agreement| agreement.classification() == classification);
+    }
     Ok(web::Json(agreements))
 }
 
+/// Returns agreement details reported by the node.
+#[utoipa::path(
+    get,
+    path = "/{role_id}/{node_id}/agreement/{agreement_id}",
+    tag = "report",
+    params(
+        ("role_id" = AgreementRole, Path, description = "Reporting side"),
+        ("node_id" = String, Path, description = "Reporting node id"),
+        ("agreement_id" = String, Path, description = "Agreement id")
+    ),
+    responses(
+        (status = 200, description = "Agreement details", body = AgreementInfo),
+        (status = 404, description = "Agreement was not reported")
+    )
+)]
 #[get("/{role_id}/{node_id}/agreement/{agreement_id}")]
 async fn get_agreement_details(
-    path: web::Path<(Role, NodeId, String)>,
+    path: web::Path<(AgreementRole, NodeId, String)>,
     data: web::Data<dao::StatusDao>,
-) -> actix_web::Result<web::Json<AgreementInfo>> {
+) -> Result<web::Json<AgreementInfo>, ApiError> {
     let (role, node_id, agreement_id) = path.into_inner();
     if let Some(agr_info) = data
-        .get_agreement_details(role.as_db(), &node_id.to_string(), &agreement_id)
-        .await
-        .map_err(actix_web::error::ErrorInternalServerError)?
+        .get_agreement_details(role_as_db(role), &node_id.to_string(), &agreement_id)
+        .await?
     {
         Ok(web::Json(agr_info))
     } else {
-        Err(actix_web::error::ErrorNotFound(format!(
+        Err(ApiError::NotFound(format!(
             "agreement {} not found",
             agreement_id
         )))
     }
 }
 
+/// Reports agreement details, has to be done once per agreement.
+#[utoipa::path(
+    post,
+    path = "/{role_id}/{node_id}/agreement/{agreement_id}",
+    tag = "report",
+    params(
+        ("role_id" = AgreementRole, Path, description = "Reporting side"),
+        ("node_id" = String, Path, description = "Reporting node id"),
+        ("agreement_id" = String, Path, description = "Agreement id")
+    ),
+    request_body = AgreementInfo,
+    responses(
+        (status = 200, description = "Details saved"),
+        (status = 409, description = "Details were already reported")
+    )
+)]
 #[post("/{role_id}/{node_id}/agreement/{agreement_id}")]
 async fn save_agreement_details(
-    path: web::Path<(Role, NodeId, String)>,
+    audit: Audit,
+    path: web::Path<(AgreementRole, NodeId, String)>,
     data: web::Data<dao::StatusDao>,
+    events: web::Data<EventHub>,
     body: web::Json<AgreementInfo>
-) -> actix_web::Result<web::Json<()>> {
+) -> Result<web::Json<()>, ApiError> {
     let (role, node_id, agreement_id) = path.into_inner();
-    let _ = data.insert_agreement(role.as_db(), node_id, &agreement_id, body.into_inner()).await
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let agreement = body.into_inner();
+    let target = format!("{}/{}/{}", role_as_db(role), node_id, agreement_id);
+    let audit_entry = audit.entry(None, "saveAgreementDetails", &target, &agreement)?;
+    let _ = data
+        .insert_agreement(
+            role_as_db(role),
+            node_id,
+            &agreement_id,
+            agreement.clone(),
+            &audit_entry,
+        )
+        .await?;
+    events.publish(ReputationEvent::AgreementReported {
+        role,
+        node_id,
+        agreement_id,
+        agreement,
+    });
     Ok(web::Json(()))
 }
 
+/// Reports current agreement status.
+#[utoipa::path(
+    post,
+    path = "/{role_id}/{node_id}/agreement/{agreement_id}/status",
+    tag = "report",
+    params(
+        ("role_id" = AgreementRole, Path, description = "Reporting side"),
+        ("node_id" = String, Path, description = "Reporting node id"),
+        ("agreement_id" = String, Path, description = "Agreement id")
+    ),
+    request_body = Status,
+    responses((status = 200, description = "Status saved", body = ReportResult))
+)]
 #[post("/{role_id}/{node_id}/agreement/{agreement_id}/status")]
 async fn save_agreement_status(
-    path: web::Path<(Role, NodeId, String)>,
+    audit: Audit,
+    path: web::Path<(AgreementRole, NodeId, String)>,
     data: web::Data<dao::StatusDao>,
+    events: web::Data<EventHub>,
     body: web::Json<Status>,
-) -> actix_web::Result<web::Json<ReportResult>> {
+) -> Result<web::Json<ReportResult>, ApiError> {
     let (role, node_id, agreement_id) = path.into_inner();
+    let target = format!("{}/{}/{}", role_as_db(role), node_id, agreement_id);
+    let audit_entry = audit.entry(None, "saveAgreementStatus", &target, &*body)?;
     let have_agreement = data
-        .insert_status(role.as_db(), node_id, &agreement_id, &body)
-        .await
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+        .insert_status(
+            role_as_db(role),
+            node_id,
+            &agreement_id,
+            &body,
+            &audit_entry,
+        )
+        .await?;
+    events.publish(ReputationEvent::StatusReported {
+        role,
+        node_id,
+        agreement_id,
+        status: body.into_inner(),
+    });
     Ok(web::Json(if have_agreement {
         ReportResult::Ok {}
     } else {
@@ -1,39 +1,174 @@
-use crate::dao;
+use crate::config::ReputationServerConfig;
+use crate::dao::{self, role_as_db, Network, ScoringAlgorithm, Segment};
+use crate::rest::error::ApiError;
 use crate::rest::ListQuery;
+use actix_web::http::header::{
+    self, CacheControl, CacheDirective, ContentType, EntityTag, IfNoneMatch,
+};
+use actix_web::http::StatusCode;
 use actix_web::web::ServiceConfig;
-use actix_web::{get, web};
+use actix_web::{get, web, HttpResponse};
+use reputation_aggregator_model::{AgreementRole, NodeId};
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, utoipa::IntoParams)]
 #[serde(rename_all = "camelCase")]
-enum Role {
-    Provider,
-    Requestor,
+#[into_params(parameter_in = Query)]
+struct ScoreQuery {
+    /// How agreements are weighted, `standard` by default
+    #[param(inline)]
+    algorithm: Option<ScoringAlgorithm>,
+    /// Score only agreements paid on this platform, e.g. `erc20-polygon-glm`
+    payment_platform: Option<String>,
+    /// Score only mainnet or testnet agreements
+    #[param(inline)]
+    network: Option<Network>,
+    /// Score only agreements in this subnet
+    subnet: Option<String>,
 }
 
-impl Role {
-    #[inline]
-    fn as_db_role(&self) -> &str {
-        match self {
-            Role::Provider => "P",
-            Role::Requestor => "R",
+impl ScoreQuery {
+    fn algorithm(&self) -> ScoringAlgorithm {
+        self.algorithm.unwrap_or_default()
+    }
+
+    fn segment(&self) -> Result<Option<Segment>, ApiError> {
+        let segments = [
+            self.payment_platform.clone().map(Segment::PaymentPlatform),
+            self.network.map(Segment::Network),
+            self.subnet.clone().map(Segment::Subnet),
+        ];
+        let mut segments = segments.into_iter().flatten();
+        match (segments.next(), segments.next()) {
+            (Some(_), None) if self.algorithm() != ScoringAlgorithm::Standard => {
+                Err(ApiError::InvalidQuery(
+                    "segments are only supported by the standard algorithm".to_string(),
+                ))
+            }
+            (segment, None) => Ok(segment),
+            _ => Err(ApiError::InvalidQuery(
+                "at most one of paymentPlatform, network and subnet can be given".to_string(),
+            )),
         }
     }
 }
 
+/// Returns standard score of the node within its role, with the evidence it is based on.
+///
+/// With `algorithm=trust` agreements are weighted by the reputation of the counterparty.
+///
+/// Scores change only when they are refreshed or moderated. Responses carry an `ETag`, a request with a
+/// matching `If-None-Match` is answered with `304 Not Modified`.
+#[utoipa::path(
+    get,
+    path = "/standard_score/{role_id}/{node_id}",
+    tag = "score",
+    params(
+        ("role_id" = AgreementRole, Path, description = "Scored side"),
+        ("node_id" = String, Path, description = "Scored node id"),
+        ScoreQuery
+    ),
+    responses(
+        (status = 200, description = "Standard score", body = StandardScore),
+        (status = 304, description = "Score has the entity tag given in `If-None-Match`")
+    )
+)]
 #[get("/standard_score/{role_id}/{node_id}")]
 async fn standard_score(
     data: web::Data<dao::StatusDao>,
-    path: web::Path<(Role, String)>,
-) -> actix_web::Result<web::Json<dao::StandardScore>> {
+    config: web::Data<ReputationServerConfig>,
+    path: web::Path<(AgreementRole, NodeId)>,
+    query: web::Query<ScoreQuery>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
+) -> Result<HttpResponse, ApiError> {
     let (role_id, node_id) = path.into_inner();
+    let segment = query.segment()?;
     let standard_score = data
-        .standard_score(role_id.as_db_role(), &node_id)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    Ok(web::Json(standard_score))
+        .standard_score(
+            role_as_db(role_id),
+            &node_id.to_string(),
+            query.algorithm(),
+            segment.as_ref(),
+        )
+        .await?;
+
+    let body =
+        serde_json::to_vec(&standard_score).map_err(|e| ApiError::Internal(e.to_string()))?;
+    let etag = EntityTag::new_strong(hex::encode(&Sha256::digest(&body)[..16]));
+    let not_modified = match if_none_match.as_deref() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header(header::ETag(etag))
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(config.score_cache.max_age as u32),
+        ]));
+    if not_modified {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).finish());
+    }
+    Ok(response.content_type(ContentType::json()).body(body))
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum RankingOrder {
+    Best,
+    Worst,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+struct RankingQuery {
+    /// Skip nodes with fewer scored agreements
+    min_agreements: Option<i64>,
+    /// Which end of the ranking to start with, `best` by default
+    #[param(inline)]
+    order: Option<RankingOrder>,
+}
+
+/// Lists nodes of the role with sufficient evidence ordered by standard score.
+#[utoipa::path(
+    get,
+    path = "/ranking/{role_id}",
+    tag = "score",
+    params(
+        ("role_id" = AgreementRole, Path, description = "Scored side"),
+        ListQuery,
+        RankingQuery,
+        ScoreQuery
+    ),
+    responses((status = 200, description = "Ranked nodes", body = [RankedNode]))
+)]
+#[get("/ranking/{role_id}")]
+async fn ranking(
+    data: web::Data<dao::StatusDao>,
+    path: web::Path<(AgreementRole,)>,
+    list: web::Query<ListQuery>,
+    query: web::Query<RankingQuery>,
+    score: web::Query<ScoreQuery>,
+) -> Result<web::Json<Vec<dao::RankedNode>>, ApiError> {
+    let (role_id,) = path.into_inner();
+    let segment = score.segment()?;
+    let ranking = data
+        .ranking(
+            role_as_db(role_id),
+            score.algorithm(),
+            segment.as_ref(),
+            query.min_agreements.unwrap_or_default(),
+            matches!(query.order, Some(RankingOrder::Worst)),
+            list.start(),
+            list.limit(),
+        )
+        .await?;
+    Ok(web::Json(ranking))
 }
 
 pub fn configure(config: &mut ServiceConfig) {
-    config.service(standard_score);
+    config.service(standard_score).service(ranking);
 }
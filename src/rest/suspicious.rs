@@ -0,0 +1,45 @@
+use crate::analysis::{self, Finding, FindingKind};
+use crate::dao;
+use crate::rest::auth::Admin;
+use crate::rest::error::ApiError;
+use actix_web::{get, web};
+use serde::Deserialize;
+
+pub fn configure(config: &mut web::ServiceConfig) {
+    config.service(suspicious);
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct SuspiciousQuery {
+    /// Only return findings of this kind
+    #[param(inline)]
+    kind: Option<FindingKind>,
+}
+
+/// Runs the sybil and collusion analysis over the current agreement graph.
+///
+/// Agreements behind the findings are down-weighted on the next score refresh.
+#[utoipa::path(
+    get,
+    path = "/admin/suspicious",
+    tag = "admin",
+    params(SuspiciousQuery),
+    responses(
+        (status = 200, description = "Suspicious node groups", body = [Finding]),
+        (status = 401, description = "Missing or invalid admin token")
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/admin/suspicious")]
+async fn suspicious(
+    _admin: Admin,
+    query: web::Query<SuspiciousQuery>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<Vec<Finding>>, ApiError> {
+    let mut findings = analysis::analyze(&data.agreement_edges().await?).findings;
+    if let Some(kind) = query.kind {
+        findings.retain(|finding| finding.kind == kind);
+    }
+    Ok(web::Json(findings))
+}
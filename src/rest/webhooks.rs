@@ -0,0 +1,156 @@
+use crate::dao::{self, Webhook};
+use crate::rest::audit::Audit;
+use crate::rest::auth::Admin;
+use crate::rest::error::ApiError;
+use crate::rest::ListQuery;
+use crate::webhooks::EVENT_TYPES;
+use actix_web::{delete, get, post, web};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::Serialize;
+
+pub fn configure(config: &mut web::ServiceConfig) {
+    config
+        .service(register_webhook)
+        .service(list_webhooks)
+        .service(delete_webhook)
+        .service(list_deliveries);
+}
+
+/// Registration response, the only place the signing secret is returned.
+#[derive(Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct RegisteredWebhook {
+    #[serde(flatten)]
+    webhook: Webhook,
+    secret: String,
+}
+
+/// Registers a webhook receiving signed event deliveries.
+///
+/// Every delivery attempt carries its Unix time in seconds in `X-Repu-Timestamp` and
+/// `sha256=<hex>` in `X-Repu-Signature`, the HMAC-SHA256 of `<timestamp>.<body>` keyed with
+/// the webhook secret. Receivers should reject deliveries more than 5 minutes off their clock.
+#[utoipa::path(
+    post,
+    path = "/admin/webhooks",
+    tag = "admin",
+    request_body = NewWebhook,
+    responses(
+        (status = 200, description = "Registered webhook with its signing secret", body = RegisteredWebhook),
+        (status = 400, description = "Unknown event type"),
+        (status = 401, description = "Missing or invalid admin token")
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/admin/webhooks")]
+async fn register_webhook(
+    admin: Admin,
+    audit: Audit,
+    data: web::Data<dao::StatusDao>,
+    body: web::Json<dao::NewWebhook>,
+) -> Result<web::Json<RegisteredWebhook>, ApiError> {
+    let new_webhook = body.into_inner();
+    if let Some(event_type) = new_webhook
+        .event_types
+        .iter()
+        .find(|event_type| !EVENT_TYPES.contains(&event_type.as_str()))
+    {
+        return Err(ApiError::BadRequest(format!(
+            "unknown event type: {}",
+            event_type
+        )));
+    }
+    let secret = new_webhook.secret.clone().unwrap_or_else(|| {
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect()
+    });
+    // Target is set to the id of the new webhook.
+    let audit_entry = audit.entry(Some(&admin), "registerWebhook", "", &new_webhook)?;
+    let webhook = data
+        .insert_webhook(&new_webhook, &secret, &admin.principal, &audit_entry)
+        .await?;
+    log::info!("webhook {} registered by {}", webhook.id, admin.principal);
+    Ok(web::Json(RegisteredWebhook { webhook, secret }))
+}
+
+/// Lists registered webhooks.
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Registered webhooks", body = [Webhook]),
+        (status = 401, description = "Missing or invalid admin token")
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/admin/webhooks")]
+async fn list_webhooks(
+    _admin: Admin,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<Vec<Webhook>>, ApiError> {
+    Ok(web::Json(data.list_webhooks().await?))
+}
+
+/// Removes a webhook together with its delivery log.
+#[utoipa::path(
+    delete,
+    path = "/admin/webhooks/{webhook_id}",
+    tag = "admin",
+    params(("webhook_id" = i64, Path, description = "Webhook id")),
+    responses(
+        (status = 200, description = "Webhook removed"),
+        (status = 401, description = "Missing or invalid admin token"),
+        (status = 404, description = "Webhook not found")
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/admin/webhooks/{webhook_id}")]
+async fn delete_webhook(
+    admin: Admin,
+    audit: Audit,
+    path: web::Path<(i64,)>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<()>, ApiError> {
+    let (webhook_id,) = path.into_inner();
+    let target = webhook_id.to_string();
+    let audit_entry = audit.entry_without_payload(Some(&admin), "deleteWebhook", &target);
+    if !data.delete_webhook(webhook_id, &audit_entry).await? {
+        return Err(ApiError::NotFound(format!(
+            "webhook {} not found",
+            webhook_id
+        )));
+    }
+    log::info!("webhook {} deleted by {}", webhook_id, admin.principal);
+    Ok(web::Json(()))
+}
+
+/// Lists delivery attempts of a webhook, newest first.
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks/{webhook_id}/deliveries",
+    tag = "admin",
+    params(("webhook_id" = i64, Path, description = "Webhook id"), ListQuery),
+    responses(
+        (status = 200, description = "Delivery attempts", body = [WebhookDelivery]),
+        (status = 401, description = "Missing or invalid admin token")
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/admin/webhooks/{webhook_id}/deliveries")]
+async fn list_deliveries(
+    _admin: Admin,
+    path: web::Path<(i64,)>,
+    query: web::Query<ListQuery>,
+    data: web::Data<dao::StatusDao>,
+) -> Result<web::Json<Vec<dao::WebhookDelivery>>, ApiError> {
+    let (webhook_id,) = path.into_inner();
+    let deliveries = data
+        .list_webhook_deliveries(webhook_id, query.start(), query.limit())
+        .await?;
+    Ok(web::Json(deliveries))
+}
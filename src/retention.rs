@@ -0,0 +1,185 @@
+//! Removal of old raw reports.
+//!
+//! Expired agreements are folded in batches: their scores are added to `agreement_archive`,
+//! summed per provider, requestor, payment platform and subnet, and their raw reports are
+//! deleted. The `calc` schema adds the archive to live agreements, so overall, segmented and
+//! trust scores and the evidence behind them keep the removed agreements. Aggregates folded
+//! into `node_archive` before only count in overall scores.
+//!
+//! A batch is only committed once its reports are written to a JSONL archive, and the archive
+//! is removed again when the commit fails. Erasing a node rewrites the archives naming it.
+
+use crate::config::RetentionConfig;
+use crate::dao::StatusDao;
+use crate::events::EventHub;
+use crate::scoring;
+use chrono::Utc;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const BATCH_SIZE: i64 = 1000;
+
+/// Archives and removes all expired agreements, returns how many were removed.
+pub async fn run(
+    dao: &StatusDao,
+    events: &EventHub,
+    config: &RetentionConfig,
+) -> anyhow::Result<usize> {
+    let before = (Utc::now() - chrono::Duration::days(config.max_age_days.into())).naive_utc();
+    // Folded scores are taken from `calc.agreement`, it has to reflect the latest reports.
+    scoring::refresh(dao, events).await?;
+
+    let mut removed = 0;
+    loop {
+        let batch = dao.fold_expired_agreements(before, BATCH_SIZE).await?;
+        let folded = batch.agreement_ids.len();
+        if folded == 0 {
+            break;
+        }
+        let path = archive(&config.archive_dir, &batch.rows)?;
+        if let Err(e) = batch.commit().await {
+            // The reports are still stored, they are archived again by the next run.
+            fs::remove_file(&path)?;
+            return Err(e.into());
+        }
+        log::info!(
+            "{} expired agreements archived to {}",
+            folded,
+            path.display()
+        );
+        removed += folded;
+    }
+    if removed > 0 {
+        scoring::refresh(dao, events).await?;
+    }
+    Ok(removed)
+}
+
+fn archive(dir: &Path, rows: &[String]) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!(
+        "reports-{}.jsonl",
+        Utc::now().format("%Y%m%dT%H%M%S%.6f")
+    ));
+    let mut file = BufWriter::new(
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?,
+    );
+    for row in rows {
+        writeln!(file, "{}", row)?;
+    }
+    file.flush()?;
+    // Reports are deleted when the batch commits, the archive has to be on disk by then.
+    file.get_ref().sync_all()?;
+    Ok(path)
+}
+
+/// Removes archived reports of all agreements naming the node, returns how many were removed.
+///
+/// Archives naming the node are rewritten to a temporary file that replaces the original, or
+/// deleted when nothing else is left in them.
+pub fn erase(dir: &Path, node_id: &str) -> std::io::Result<u64> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension() == Some("jsonl".as_ref()) {
+            removed += erase_from(&path, node_id)?;
+        }
+    }
+    Ok(removed)
+}
+
+fn erase_from(path: &Path, node_id: &str) -> std::io::Result<u64> {
+    let lines = BufReader::new(fs::File::open(path)?)
+        .lines()
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let rows = lines
+        .iter()
+        .map(|line| serde_json::from_str::<Value>(line))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Every report of an agreement goes, not only those the node itself sent.
+    let agreements: HashSet<&str> = rows
+        .iter()
+        .filter(|row| names_node(&row["row"], node_id))
+        .filter_map(|row| row["row"]["agreement_id"].as_str())
+        .collect();
+    if agreements.is_empty() {
+        return Ok(0);
+    }
+    let kept: Vec<&String> = lines
+        .iter()
+        .zip(&rows)
+        .filter(|(_, row)| {
+            !matches!(row["row"]["agreement_id"].as_str(), Some(id) if agreements.contains(id))
+        })
+        .map(|(line, _)| line)
+        .collect();
+    if kept.is_empty() {
+        fs::remove_file(path)?;
+    } else {
+        let tmp = path.with_extension("jsonl.tmp");
+        let mut file = BufWriter::new(fs::File::create(&tmp)?);
+        for line in &kept {
+            writeln!(file, "{}", line)?;
+        }
+        file.flush()?;
+        file.get_ref().sync_all()?;
+        fs::rename(&tmp, path)?;
+    }
+    Ok((lines.len() - kept.len()) as u64)
+}
+
+fn names_node(row: &Value, node_id: &str) -> bool {
+    match row {
+        Value::String(s) => s.eq_ignore_ascii_case(node_id),
+        Value::Array(values) => values.iter().any(|v| names_node(v, node_id)),
+        Value::Object(fields) => fields.values().any(|v| names_node(v, node_id)),
+        _ => false,
+    }
+}
+
+/// Periodically enforces the retention policy until the runtime is stopped.
+pub fn spawn(dao: StatusDao, events: actix_web::web::Data<EventHub>, config: RetentionConfig) {
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(Duration::from_secs(config.interval));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run(&dao, &events, &config).await {
+                log::error!("failed to enforce retention: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn erase_drops_every_report_of_agreements_naming_the_node() {
+        let dir = std::env::temp_dir().join(format!("retention-erase-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rows = [
+            r#"{"table":"agreement_details","row":{"agreement_id":"a1","node_id":"0xAA","peer_id":"0xbb"}}"#,
+            r#"{"table":"agreement_status","row":{"agreement_id":"a1","node_id":"0xbb","status":"Paid"}}"#,
+            r#"{"table":"agreement_status","row":{"agreement_id":"a2","node_id":"0xbb","status":"Paid"}}"#,
+        ];
+        let kept = archive(&dir, &rows.map(str::to_string)).unwrap();
+        let gone = archive(&dir, &[rows[0].to_string()]).unwrap();
+
+        assert_eq!(erase(&dir, "0xaa").unwrap(), 3);
+        assert_eq!(fs::read_to_string(&kept).unwrap(), format!("{}\n", rows[2]));
+        assert!(!gone.exists());
+        assert_eq!(erase(&dir, "0xaa").unwrap(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+//! In-process cache of score lookups.
+//!
+//! Scores only change when they are refreshed or moderated, and the DAO drops every entry on
+//! such writes. Entries also expire after `max_age`. That bounds how stale a score gets when
+//! another process changes it, e.g. `refresh-scores`, or when a lagging replica is read right
+//! after a refresh.
+
+use crate::config::ScoreCacheConfig;
+use crate::dao::StandardScore;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Everything a score lookup depends on: role, node, segment type and segment as bound to the
+/// query.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ScoreKey {
+    pub role_id: String,
+    pub node_id: String,
+    pub segment_type: &'static str,
+    pub segment: String,
+}
+
+#[derive(Clone)]
+pub(crate) struct ScoreCache {
+    max_entries: usize,
+    max_age: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Bumped on invalidation, so that lookups started before it are not stored.
+    generation: u64,
+    entries: HashMap<ScoreKey, (Instant, StandardScore)>,
+}
+
+impl ScoreCache {
+    pub fn new(config: &ScoreCacheConfig) -> Self {
+        ScoreCache {
+            max_entries: config.max_entries,
+            max_age: Duration::from_secs(config.max_age),
+            state: Default::default(),
+        }
+    }
+
+    pub fn get(&self, key: &ScoreKey) -> Option<StandardScore> {
+        let state = self.state.lock().unwrap();
+        let (stored, score) = state.entries.get(key)?;
+        Some(score.clone()).filter(|_| stored.elapsed() < self.max_age)
+    }
+
+    /// To be taken before looking up a score that is going to be stored.
+    pub fn generation(&self) -> u64 {
+        self.state.lock().unwrap().generation
+    }
+
+    /// Stores the score unless the cache was invalidated since `generation` or is full.
+    pub fn insert(&self, key: ScoreKey, generation: u64, score: &StandardScore) {
+        let mut state = self.state.lock().unwrap();
+        if state.generation != generation {
+            return;
+        }
+        if state.entries.len() >= self.max_entries {
+            let max_age = self.max_age;
+            state
+                .entries
+                .retain(|_, (stored, _)| stored.elapsed() < max_age);
+            if state.entries.len() >= self.max_entries {
+                return;
+            }
+        }
+        state.entries.insert(key, (Instant::now(), score.clone()));
+    }
+
+    pub fn invalidate(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        state.entries.clear();
+    }
+}
@@ -0,0 +1,97 @@
+use crate::dao::{role_from_db, StatusDao};
+use crate::events::EventHub;
+use crate::{analysis, trust};
+use reputation_aggregator_model::{
+    AgreementClassification, AgreementRole, NodeId, ReputationEvent,
+};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Flags suspicious agreements, recalculates scores and publishes [`ReputationEvent::ScoreChanged`] for every node
+/// whose standard score is different after the refresh, and
+/// [`ReputationEvent::AgreementClassified`] for every new or reclassified agreement.
+pub async fn refresh(dao: &StatusDao, events: &EventHub) -> sqlx::Result<()> {
+    let mut scores_before: HashMap<_, _> = dao
+        .node_scores()
+        .await?
+        .into_iter()
+        .map(|score| ((score.role_id, score.node_id), score.standard_score))
+        .collect();
+    let mut results_before: HashMap<_, _> = dao
+        .agreement_results()
+        .await?
+        .into_iter()
+        .map(|result| (result.agreement_id, result.agreement_result))
+        .collect();
+    let analysis = analysis::analyze(&dao.agreement_edges().await?);
+    dao.replace_agreement_flags(&analysis.flags).await?;
+    dao.refresh_scores().await?;
+    dao.replace_trust_scores(&trust::compute(&dao.agreement_scores().await?))
+        .await?;
+    dao.refresh_ranking().await?;
+
+    let mut changed = 0;
+    for score in dao.node_scores().await? {
+        let key = (score.role_id, score.node_id);
+        let previous = scores_before.remove(&key).flatten();
+        if previous == score.standard_score {
+            continue;
+        }
+        let (role_id, node_id) = key;
+        if let (Some(role), Ok(node_id)) = (role_from_db(&role_id), node_id.parse::<NodeId>()) {
+            changed += 1;
+            events.publish(ReputationEvent::ScoreChanged {
+                role,
+                node_id,
+                previous,
+                score: score.standard_score,
+            });
+        }
+    }
+
+    let mut classified = 0;
+    for result in dao.agreement_results().await? {
+        if results_before.remove(&result.agreement_id).as_ref() == Some(&result.agreement_result) {
+            continue;
+        }
+        let (provider_id, requestor_id) =
+            match (result.p_id.parse::<NodeId>(), result.r_id.parse::<NodeId>()) {
+                (Ok(provider_id), Ok(requestor_id)) => (provider_id, requestor_id),
+                _ => continue,
+            };
+        let classification = AgreementClassification::from(result.agreement_result.as_str());
+        classified += 1;
+        for (role, node_id, peer_id) in [
+            (AgreementRole::Provider, provider_id, requestor_id),
+            (AgreementRole::Requestor, requestor_id, provider_id),
+        ] {
+            events.publish(ReputationEvent::AgreementClassified {
+                role,
+                node_id,
+                agreement_id: result.agreement_id.clone(),
+                peer_id,
+                classification,
+            });
+        }
+    }
+    log::debug!(
+        "scores refreshed, {} scores changed, {} agreements classified, {} agreements flagged",
+        changed,
+        classified,
+        analysis.flags.len()
+    );
+    Ok(())
+}
+
+/// Periodically refreshes scores until the runtime is stopped.
+pub fn spawn_refresher(dao: StatusDao, events: actix_web::web::Data<EventHub>, every: Duration) {
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(every);
+        loop {
+            interval.tick().await;
+            if let Err(e) = refresh(&dao, &events).await {
+                log::error!("failed to refresh scores: {}", e);
+            }
+        }
+    });
+}
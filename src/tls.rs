@@ -0,0 +1,112 @@
+//! TLS termination with the certificate reloaded from disk when it is renewed.
+//!
+//! Renewal tools replace the certificate and key files in place. Their modification times are
+//! checked periodically and, once both files load, new connections get the new certificate.
+//! Established connections keep the one they were started with.
+
+use crate::config::TlsConfig;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::{self, CertifiedKey};
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+/// Builds the server configuration and starts watching the certificate files.
+pub fn server_config(config: &TlsConfig) -> anyhow::Result<ServerConfig> {
+    let resolver = Arc::new(CertResolver {
+        current: RwLock::new(Arc::new(load(config)?)),
+    });
+    if config.reload_interval > 0 {
+        spawn_reloader(resolver.clone(), config.clone());
+    }
+    Ok(ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver))
+}
+
+/// Checks that the certificate and key load.
+pub fn check(config: &TlsConfig) -> anyhow::Result<()> {
+    load(config).map(|_| ())
+}
+
+struct CertResolver {
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+fn spawn_reloader(resolver: Arc<CertResolver>, config: TlsConfig) {
+    actix_rt::spawn(async move {
+        let mut loaded = modified(&config);
+        let mut interval = actix_rt::time::interval(Duration::from_secs(config.reload_interval));
+        // The first tick completes immediately.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let current = modified(&config);
+            if current == loaded {
+                continue;
+            }
+            // Failures are retried on the next tick, renewal may be half way through.
+            match load(&config) {
+                Ok(key) => {
+                    *resolver.current.write().unwrap() = Arc::new(key);
+                    loaded = current;
+                    log::info!(
+                        "reloaded TLS certificate from {}",
+                        config.cert_path.display()
+                    );
+                }
+                Err(e) => log::warn!(
+                    "failed to reload TLS certificate, keeping the previous one: {}",
+                    e
+                ),
+            }
+        }
+    });
+}
+
+fn modified(config: &TlsConfig) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    (modified(&config.cert_path), modified(&config.key_path))
+}
+
+fn load(config: &TlsConfig) -> anyhow::Result<CertifiedKey> {
+    let certs = rustls_pemfile::certs(&mut open(&config.cert_path)?)?;
+    if certs.is_empty() {
+        anyhow::bail!("no certificate in {}", config.cert_path.display());
+    }
+    let key = read_key(&config.key_path)?;
+    let key = sign::any_supported_type(&key)
+        .map_err(|_| anyhow::anyhow!("unsupported key in {}", config.key_path.display()))?;
+    Ok(CertifiedKey::new(
+        certs.into_iter().map(Certificate).collect(),
+        key,
+    ))
+}
+
+fn read_key(path: &Path) -> anyhow::Result<PrivateKey> {
+    for item in rustls_pemfile::read_all(&mut open(path)?)? {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => (),
+        }
+    }
+    anyhow::bail!("no private key in {}", path.display())
+}
+
+fn open(path: &Path) -> anyhow::Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| anyhow::anyhow!("cannot open {}: {}", path.display(), e))
+}
@@ -0,0 +1,320 @@
+//! Counterparty-weighted scoring, EigenTrust over the provider-requestor graph.
+//!
+//! Requestors trust providers in proportion to the positive provider scores of their
+//! agreements, and providers trust requestors in proportion to the positive requestor
+//! scores. Global trust is the stationary distribution of that trust passing back and forth,
+//! with a share of it always spread evenly over all nodes so that the iteration converges and
+//! newcomers are not stuck at zero.
+//!
+//! The score of a node is then the sum of its agreement scores, each weighted by the trust of
+//! the counterparty relative to an average node of that role. Praise from a throwaway node that
+//! nobody else deals with is worth little.
+
+use crate::dao::AgreementScore;
+use std::collections::HashMap;
+
+/// Share of trust spread evenly over all nodes in every iteration.
+const PRE_TRUST: f64 = 0.15;
+const MAX_ITERATIONS: usize = 100;
+const TOLERANCE: f64 = 1e-9;
+
+/// Counterparty-weighted score of a node in one of its roles.
+#[derive(Debug, Clone)]
+pub struct TrustScore {
+    pub role_id: &'static str,
+    pub node_id: String,
+    /// Share of the global trust of the role, sums to 1 over all nodes of the role.
+    pub trust: f64,
+    pub raw_score: f64,
+    pub standard_score: Option<f64>,
+}
+
+/// Nodes of one role, numbered in order of appearance.
+#[derive(Default)]
+struct Nodes<'a> {
+    index: HashMap<&'a str, usize>,
+    ids: Vec<&'a str>,
+}
+
+impl<'a> Nodes<'a> {
+    fn insert(&mut self, node_id: &'a str) -> usize {
+        let ids = &mut self.ids;
+        *self.index.entry(node_id).or_insert_with(|| {
+            ids.push(node_id);
+            ids.len() - 1
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+/// Row normalized local trust, `links[i]` lists `(j, c_ij)` of truster `i`.
+struct LocalTrust {
+    links: Vec<Vec<(usize, f64)>>,
+}
+
+impl LocalTrust {
+    fn new(trusters: usize, satisfaction: HashMap<(usize, usize), f64>) -> Self {
+        let mut links = vec![Vec::new(); trusters];
+        for ((i, j), value) in satisfaction {
+            if value > 0.0 {
+                links[i].push((j, value));
+            }
+        }
+        for row in &mut links {
+            let total: f64 = row.iter().map(|(_, value)| value).sum();
+            for (_, value) in row.iter_mut() {
+                *value /= total;
+            }
+        }
+        LocalTrust { links }
+    }
+
+    /// Trust passed from `trusters` to `size` trusted nodes. Trusters without any positive
+    /// experience spread their trust evenly.
+    fn propagate(&self, trusters: &[f64], size: usize) -> Vec<f64> {
+        let mut trust = vec![0.0; size];
+        let mut spread = 0.0;
+        for (row, truster) in self.links.iter().zip(trusters) {
+            if row.is_empty() {
+                spread += truster;
+            }
+            for (j, value) in row {
+                trust[*j] += truster * value;
+            }
+        }
+        let pre_trust = 1.0 / size as f64;
+        for value in &mut trust {
+            *value = PRE_TRUST * pre_trust + (1.0 - PRE_TRUST) * (*value + spread * pre_trust);
+        }
+        trust
+    }
+}
+
+fn standardize(raw_scores: &[f64]) -> Vec<Option<f64>> {
+    let n = raw_scores.len() as f64;
+    let mean = raw_scores.iter().sum::<f64>() / n;
+    let variance = raw_scores
+        .iter()
+        .map(|score| (score - mean).powi(2))
+        .sum::<f64>()
+        / (n - 1.0);
+    let stddev = variance.sqrt();
+    raw_scores
+        .iter()
+        .map(|score| Some((score - mean) / stddev).filter(|score| score.is_finite()))
+        .collect()
+}
+
+pub fn compute(agreements: &[AgreementScore]) -> Vec<TrustScore> {
+    let mut providers = Nodes::default();
+    let mut requestors = Nodes::default();
+    let mut provider_satisfaction = HashMap::new();
+    let mut requestor_satisfaction = HashMap::new();
+    let edges: Vec<_> = agreements
+        .iter()
+        .map(|agreement| {
+            let p = providers.insert(&agreement.p_id);
+            let r = requestors.insert(&agreement.r_id);
+            // Requestor is satisfied with the provider when the provider scores, and vice versa.
+            *requestor_satisfaction.entry((r, p)).or_insert(0.0) += agreement.p_score;
+            *provider_satisfaction.entry((p, r)).or_insert(0.0) += agreement.r_score;
+            (p, r)
+        })
+        .collect();
+    if edges.is_empty() {
+        return Vec::new();
+    }
+
+    let provider_trusts = LocalTrust::new(providers.len(), provider_satisfaction);
+    let requestor_trusts = LocalTrust::new(requestors.len(), requestor_satisfaction);
+    let mut provider_trust = vec![1.0 / providers.len() as f64; providers.len()];
+    let mut requestor_trust = vec![1.0 / requestors.len() as f64; requestors.len()];
+    for iteration in 0..MAX_ITERATIONS {
+        let next_provider_trust = requestor_trusts.propagate(&requestor_trust, providers.len());
+        let next_requestor_trust = provider_trusts.propagate(&provider_trust, requestors.len());
+        let delta: f64 = next_provider_trust
+            .iter()
+            .zip(&provider_trust)
+            .chain(next_requestor_trust.iter().zip(&requestor_trust))
+            .map(|(next, previous)| (next - previous).abs())
+            .sum();
+        provider_trust = next_provider_trust;
+        requestor_trust = next_requestor_trust;
+        if delta < TOLERANCE {
+            log::trace!("trust converged after {} iterations", iteration + 1);
+            break;
+        }
+    }
+
+    // Counterparty weight is relative to an average node, so that the scale matches raw scores.
+    let mut provider_raw = vec![0.0; providers.len()];
+    let mut requestor_raw = vec![0.0; requestors.len()];
+    for (agreement, (p, r)) in agreements.iter().zip(edges) {
+        provider_raw[p] += agreement.p_score * requestor_trust[r] * requestors.len() as f64;
+        requestor_raw[r] += agreement.r_score * provider_trust[p] * providers.len() as f64;
+    }
+
+    let mut scores = Vec::with_capacity(providers.len() + requestors.len());
+    for (role_id, nodes, trust, raw) in [
+        ("P", &providers, &provider_trust, &provider_raw),
+        ("R", &requestors, &requestor_trust, &requestor_raw),
+    ] {
+        for (i, standard_score) in standardize(raw).into_iter().enumerate() {
+            scores.push(TrustScore {
+                role_id,
+                node_id: nodes.ids[i].to_string(),
+                trust: trust[i],
+                raw_score: raw[i],
+                standard_score,
+            });
+        }
+    }
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agreement(p_id: &str, r_id: &str, p_score: f64, r_score: f64) -> AgreementScore {
+        AgreementScore {
+            p_id: p_id.to_string(),
+            r_id: r_id.to_string(),
+            p_score,
+            r_score,
+        }
+    }
+
+    fn trust(scores: &[TrustScore], role_id: &str) -> Vec<f64> {
+        scores
+            .iter()
+            .filter(|score| score.role_id == role_id)
+            .map(|score| score.trust)
+            .collect()
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(
+                (actual - expected).abs() < 1e-6,
+                "{actual:?} != {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn empty_graph() {
+        assert!(compute(&[]).is_empty());
+    }
+
+    #[test]
+    fn converges_to_fixed_point() {
+        let agreements = [
+            agreement("p1", "r1", 1.0, 0.5),
+            agreement("p1", "r2", 2.0, 1.0),
+            agreement("p2", "r2", 0.5, 2.0),
+            agreement("p2", "r3", 1.0, 1.0),
+            agreement("p3", "r3", 3.0, 0.5),
+        ];
+
+        let scores = compute(&agreements);
+        let provider_trust = trust(&scores, "P");
+        let requestor_trust = trust(&scores, "R");
+
+        assert_close(&[provider_trust.iter().sum()], &[1.0]);
+        assert_close(&[requestor_trust.iter().sum()], &[1.0]);
+        // Another round of propagation leaves the trust as it is.
+        let requestor_trusts = LocalTrust::new(
+            3,
+            HashMap::from([
+                ((0, 0), 1.0),
+                ((1, 0), 2.0),
+                ((1, 1), 0.5),
+                ((2, 1), 1.0),
+                ((2, 2), 3.0),
+            ]),
+        );
+        let provider_trusts = LocalTrust::new(
+            3,
+            HashMap::from([
+                ((0, 0), 0.5),
+                ((0, 1), 1.0),
+                ((1, 1), 2.0),
+                ((1, 2), 1.0),
+                ((2, 2), 0.5),
+            ]),
+        );
+        assert_close(
+            &requestor_trusts.propagate(&requestor_trust, 3),
+            &provider_trust,
+        );
+        assert_close(
+            &provider_trusts.propagate(&provider_trust, 3),
+            &requestor_trust,
+        );
+        assert!(scores.iter().all(|score| score.standard_score.is_some()));
+    }
+
+    #[test]
+    fn pre_trust_keeps_untrusted_nodes_above_zero() {
+        // Nobody is satisfied with p2, it only gets its share of pre-trust.
+        let agreements = [
+            agreement("p1", "r1", 1.0, 1.0),
+            agreement("p1", "r2", 1.0, 1.0),
+            agreement("p2", "r2", -1.0, 1.0),
+        ];
+
+        let scores = compute(&agreements);
+
+        let (p1, p2) = (1.0 - PRE_TRUST / 2.0, PRE_TRUST / 2.0);
+        assert_close(&trust(&scores, "P"), &[p1, p2]);
+        assert_close(
+            &trust(&scores, "R"),
+            &[
+                PRE_TRUST / 2.0 + (1.0 - PRE_TRUST) * p1 / 2.0,
+                PRE_TRUST / 2.0 + (1.0 - PRE_TRUST) * (p1 / 2.0 + p2),
+            ],
+        );
+    }
+
+    #[test]
+    fn summed_agreements_score_like_separate_ones() {
+        // Retention keeps agreements of a provider-requestor pair as one summed row.
+        let separate = [
+            agreement("p1", "r1", 1.0, 0.5),
+            agreement("p1", "r1", 2.0, -0.5),
+            agreement("p2", "r1", 0.5, 1.0),
+            agreement("p2", "r2", 1.0, 1.0),
+        ];
+        let summed = [
+            agreement("p1", "r1", 3.0, 0.0),
+            agreement("p2", "r1", 0.5, 1.0),
+            agreement("p2", "r2", 1.0, 1.0),
+        ];
+
+        let separate = compute(&separate);
+        let summed = compute(&summed);
+
+        for role_id in ["P", "R"] {
+            assert_close(&trust(&summed, role_id), &trust(&separate, role_id));
+        }
+        let raw = |scores: &[TrustScore]| -> Vec<f64> {
+            scores.iter().map(|score| score.raw_score).collect()
+        };
+        assert_close(&raw(&summed), &raw(&separate));
+    }
+
+    #[test]
+    fn trusters_without_positive_experience_spread_evenly() {
+        let local = LocalTrust::new(2, HashMap::from([((0, 0), 1.0), ((1, 1), -1.0)]));
+
+        let trust = local.propagate(&[0.5, 0.5], 2);
+
+        let spread = PRE_TRUST / 2.0 + (1.0 - PRE_TRUST) * 0.25;
+        assert_close(&trust, &[spread + (1.0 - PRE_TRUST) * 0.5, spread]);
+    }
+}
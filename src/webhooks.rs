@@ -0,0 +1,179 @@
+use crate::dao::{StatusDao, Webhook};
+use crate::events::EventHub;
+use hmac::{Hmac, Mac};
+use reputation_aggregator_model::ReputationEvent;
+use sha2::Sha256;
+use sqlx::types::BigDecimal;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Semaphore;
+
+/// Event types webhooks can subscribe to.
+pub const EVENT_TYPES: &[&str] = &[
+    "agreementReported",
+    "statusReported",
+    "scoreChanged",
+    "agreementClassified",
+];
+
+/// Hex encoded HMAC-SHA256 of `<timestamp>.<payload>`, sent as `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "X-Repu-Signature";
+/// Unix time of the delivery attempt, in seconds.
+pub const TIMESTAMP_HEADER: &str = "X-Repu-Timestamp";
+pub const EVENT_HEADER: &str = "X-Repu-Event";
+/// Receivers should reject deliveries whose timestamp is further than this from their clock,
+/// so that a captured delivery cannot be replayed later.
+pub const SIGNATURE_TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+const MAX_ATTEMPTS: i32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+/// Deliveries in progress, retries included. The worker waits for a free slot meanwhile, with
+/// new events held by the event hub up to its capacity.
+const MAX_CONCURRENT_DELIVERIES: usize = 32;
+
+fn above(threshold: &BigDecimal, score: &Option<BigDecimal>) -> bool {
+    score.as_ref().is_some_and(|score| score >= threshold)
+}
+
+/// Checks webhook filters against the event.
+pub fn matches(webhook: &Webhook, event: &ReputationEvent) -> bool {
+    if !webhook.event_types.is_empty()
+        && !webhook.event_types.iter().any(|t| t == event.event_type())
+    {
+        return false;
+    }
+    if webhook.role.is_some_and(|role| role != event.role()) {
+        return false;
+    }
+    if let Some(node_id) = &webhook.node_id {
+        if !node_id.eq_ignore_ascii_case(&event.node_id().to_string()) {
+            return false;
+        }
+    }
+    match event {
+        ReputationEvent::ScoreChanged {
+            previous, score, ..
+        } => webhook
+            .threshold
+            .as_ref()
+            .is_none_or(|threshold| above(threshold, previous) != above(threshold, score)),
+        ReputationEvent::AgreementClassified { classification, .. } => webhook
+            .classification
+            .is_none_or(|expected| expected == *classification),
+        _ => true,
+    }
+}
+
+pub fn sign(secret: &str, timestamp: u64, payload: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Delivers every published event to all matching webhooks.
+pub fn spawn_delivery_worker(dao: StatusDao, events: &EventHub) {
+    let mut receiver = events.subscribe();
+    actix_rt::spawn(async move {
+        let client = awc::Client::new();
+        let slots = Arc::new(Semaphore::new(MAX_CONCURRENT_DELIVERIES));
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("webhook worker lagged, {} events not delivered", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let webhooks = match dao.cached_webhooks().await {
+                Ok(webhooks) => webhooks,
+                Err(e) => {
+                    log::error!("failed to load webhooks: {}", e);
+                    continue;
+                }
+            };
+            for webhook in webhooks.iter().filter(|webhook| matches(webhook, &event)) {
+                let slot = slots
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("delivery semaphore is never closed");
+                let delivery = deliver(dao.clone(), client.clone(), webhook.clone(), event.clone());
+                actix_rt::spawn(async move {
+                    delivery.await;
+                    drop(slot);
+                });
+            }
+        }
+    });
+}
+
+async fn deliver(dao: StatusDao, client: awc::Client, webhook: Webhook, event: ReputationEvent) {
+    let payload = match serde_json::to_string(&event) {
+        Ok(payload) => payload,
+        Err(e) => {
+            log::error!("failed to serialize event: {}", e);
+            return;
+        }
+    };
+    let mut backoff = INITIAL_BACKOFF;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        // Every attempt is signed with its own time, so that retries pass the tolerance check.
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let signature = format!(
+            "sha256={}",
+            sign(&webhook.secret, timestamp, payload.as_bytes())
+        );
+        let result = client
+            .post(&webhook.url)
+            .insert_header(("Content-Type", "application/json"))
+            .insert_header((TIMESTAMP_HEADER, timestamp.to_string()))
+            .insert_header((SIGNATURE_HEADER, signature.as_str()))
+            .insert_header((EVENT_HEADER, event.event_type()))
+            .send_body(payload.clone())
+            .await;
+        let (status_code, error) = match result {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16() as i32), None)
+            }
+            Ok(response) => (
+                Some(response.status().as_u16() as i32),
+                Some(format!("bad response: {}", response.status())),
+            ),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        if let Err(e) = dao
+            .insert_webhook_delivery(
+                webhook.id,
+                event.event_type(),
+                &payload,
+                attempt,
+                status_code,
+                error.as_deref(),
+            )
+            .await
+        {
+            log::error!("failed to log webhook {} delivery: {}", webhook.id, e);
+        }
+        if error.is_none() {
+            return;
+        }
+        if attempt < MAX_ATTEMPTS {
+            actix_rt::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+    log::warn!(
+        "giving up webhook {} delivery after {} attempts",
+        webhook.id,
+        MAX_ATTEMPTS
+    );
+}
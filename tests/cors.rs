@@ -0,0 +1,79 @@
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::{test, App};
+use reputation_aggregator_mock::config::CorsConfig;
+use reputation_aggregator_mock::rest;
+
+fn preflight(origin: &str) -> test::TestRequest {
+    test::TestRequest::default()
+        .method(Method::OPTIONS)
+        .uri("/api")
+        .insert_header((header::ORIGIN, origin))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "GET"))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization"))
+}
+
+#[actix_web::test]
+async fn test_allowed_origins() {
+    let config = CorsConfig {
+        allowed_origins: vec!["https://dash.example".to_string()],
+        max_age: Some(600),
+    };
+    let app = test::init_service(
+        App::new()
+            .wrap(rest::cors(&config))
+            .configure(rest::configure),
+    )
+    .await;
+
+    let resp = test::call_service(&app, preflight("https://dash.example").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .unwrap(),
+        "https://dash.example"
+    );
+    assert_eq!(
+        resp.headers().get(header::ACCESS_CONTROL_MAX_AGE).unwrap(),
+        "600"
+    );
+
+    let resp = test::call_service(&app, preflight("https://other.example").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/api")
+            .insert_header((header::ORIGIN, "https://dash.example"))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .unwrap(),
+        "https://dash.example"
+    );
+}
+
+#[actix_web::test]
+async fn test_any_origin() {
+    let config = CorsConfig {
+        allowed_origins: vec!["*".to_string()],
+        max_age: None,
+    };
+    let app = test::init_service(
+        App::new()
+            .wrap(rest::cors(&config))
+            .configure(rest::configure),
+    )
+    .await;
+
+    let resp = test::call_service(&app, preflight("https://anywhere.example").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp
+        .headers()
+        .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+}
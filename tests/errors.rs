@@ -0,0 +1,42 @@
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use reputation_aggregator_mock::config::ReputationServerConfig;
+use reputation_aggregator_mock::rest;
+use reputation_aggregator_model::{ErrorCode, ErrorResponse};
+
+async fn get_error(uri: &str) -> (StatusCode, ErrorResponse) {
+    let config: ReputationServerConfig = serde_json::from_value(serde_json::json!({
+        "listen_on": "127.0.0.1:8080",
+        "apply_migrations": false,
+        "database_url": "postgres://localhost/repu",
+        "admin_tokens": {"admin": "secret"}
+    }))
+    .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .configure(rest::configure),
+    )
+    .await;
+    let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+    let status = resp.status();
+    (status, test::read_body_json(resp).await)
+}
+
+#[actix_web::test]
+async fn test_invalid_path() {
+    let (status, error) = get_error("/api/v1/consumer").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error.code, ErrorCode::InvalidPath);
+
+    let (status, error) = get_error("/api/v1/provider/0xnotanode/agreement").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error.code, ErrorCode::InvalidPath);
+}
+
+#[actix_web::test]
+async fn test_unauthorized() {
+    let (status, error) = get_error("/api/v1/admin/webhooks").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(error.code, ErrorCode::Unauthorized);
+}
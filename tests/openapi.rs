@@ -0,0 +1,32 @@
+use actix_web::{test, App};
+use reputation_aggregator_mock::rest;
+use std::path::Path;
+
+/// Fails when the served spec differs from the committed `openapi.json`.
+///
+/// Run with `UPDATE_OPENAPI=1` to regenerate the committed file.
+#[actix_web::test]
+async fn test_openapi_up_to_date() {
+    let app = test::init_service(App::new().configure(rest::configure)).await;
+    let served: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        test::TestRequest::get()
+            .uri("/api/v1/openapi.json")
+            .to_request(),
+    )
+    .await;
+
+    let committed_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("openapi.json");
+    if std::env::var_os("UPDATE_OPENAPI").is_some() {
+        let mut spec = serde_json::to_string_pretty(&served).unwrap();
+        spec.push('\n');
+        std::fs::write(&committed_path, spec).unwrap();
+        return;
+    }
+    let committed: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&committed_path).unwrap()).unwrap();
+    assert!(
+        served == committed,
+        "served OpenAPI spec differs from openapi.json, rerun with UPDATE_OPENAPI=1 and commit the result"
+    );
+}
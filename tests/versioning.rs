@@ -0,0 +1,46 @@
+use actix_web::{test, App};
+use reputation_aggregator_mock::rest;
+use reputation_aggregator_model::ApiVersions;
+
+#[actix_web::test]
+async fn test_versions() {
+    let app = test::init_service(App::new().configure(rest::configure)).await;
+    let versions: ApiVersions =
+        test::call_and_read_body_json(&app, test::TestRequest::get().uri("/api").to_request())
+            .await;
+    assert_eq!(versions.versions, vec!["v1".to_string()]);
+}
+
+#[actix_web::test]
+async fn test_legacy_alias_is_deprecated() {
+    let app = test::init_service(App::new().configure(rest::configure)).await;
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/api/v1/openapi.json")
+            .to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    assert!(resp.headers().get("Deprecation").is_none());
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get().uri("/openapi.json").to_request(),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    assert_eq!(resp.headers().get("Deprecation").unwrap(), "true");
+}
+
+#[actix_web::test]
+async fn test_static_paths_not_aliased() {
+    let app = test::init_service(App::new().configure(rest::configure)).await;
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get().uri("/index.html").to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+}
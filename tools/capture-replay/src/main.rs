@@ -0,0 +1,192 @@
+use actix_rt::time::{sleep_until, Instant};
+use chrono::Utc;
+use futures::prelude::*;
+use reputation_aggregator_mock::capture::{self, CaptureRecord, CapturedRequest};
+use reputation_aggregator_mock::dao::{role_as_db, NewAuditEntry, StatusDao};
+use reputation_aggregator_model::RepuAggrClient;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use structopt::StructOpt;
+
+#[derive(Debug, Clone, Copy)]
+enum TimestampMode {
+    /// Send timestamps as captured.
+    Preserve,
+    /// Move all timestamps so that the first record happens now.
+    Shift,
+}
+
+impl FromStr for TimestampMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "preserve" => Self::Preserve,
+            "shift" => Self::Shift,
+            _ => return Err(format!("unknown timestamp mode: {}", s)),
+        })
+    }
+}
+
+/// Replays JSONL capture of agreement and status reports.
+#[derive(Debug, StructOpt)]
+struct Args {
+    /// JSONL capture file
+    capture: PathBuf,
+    #[structopt(long, default_value = "http://127.0.0.1:8080")]
+    /// Reputation server to send reports to
+    url: String,
+    #[structopt(long)]
+    /// Write reports directly into the database instead of sending them to `--url`
+    database_url: Option<String>,
+    #[structopt(long, default_value = "shift", possible_values = &["preserve", "shift"])]
+    /// How to treat captured timestamps
+    timestamps: TimestampMode,
+    #[structopt(long, conflicts_with = "speed")]
+    /// Maximum number of requests per second
+    rate: Option<f64>,
+    #[structopt(long)]
+    /// Replay with captured request timing, scaled by the given factor (2 = twice as fast)
+    speed: Option<f64>,
+    #[structopt(long, default_value = "4")]
+    /// Maximum number of requests in flight
+    concurrency: usize,
+}
+
+enum Target {
+    Http(RepuAggrClient),
+    Dao(StatusDao),
+}
+
+impl Target {
+    /// Returns `false` when status was reported for an agreement without details.
+    async fn send(&self, record: CaptureRecord) -> Result<bool, Box<dyn Error>> {
+        let CaptureRecord {
+            role,
+            node_id,
+            agreement_id,
+            request,
+            ..
+        } = record;
+        let target = format!("{}/{}/{}", role_as_db(role), node_id, agreement_id);
+        Ok(match (self, request) {
+            (Target::Http(client), CapturedRequest::Agreement(info)) => {
+                client.agreement(role, node_id, &agreement_id, info).await?;
+                true
+            }
+            (Target::Http(client), CapturedRequest::Status(status)) => !client
+                .report(role, node_id, &agreement_id, status)
+                .await?
+                .is_unknown_agreement(),
+            (Target::Dao(dao), CapturedRequest::Agreement(info)) => {
+                let audit_entry =
+                    NewAuditEntry::cli("saveAgreementDetails", &target).with_payload(&info)?;
+                dao.insert_agreement(role_as_db(role), node_id, &agreement_id, info, &audit_entry)
+                    .await?;
+                true
+            }
+            (Target::Dao(dao), CapturedRequest::Status(status)) => {
+                let audit_entry =
+                    NewAuditEntry::cli("saveAgreementStatus", &target).with_payload(&status)?;
+                dao.insert_status(
+                    role_as_db(role),
+                    node_id,
+                    &agreement_id,
+                    &status,
+                    &audit_entry,
+                )
+                .await?
+            }
+        })
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    sent: usize,
+    failed: usize,
+    unknown_agreement: usize,
+}
+
+#[actix_rt::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::from_args();
+    env_logger::init();
+
+    let mut records = capture::read_records(BufReader::new(File::open(&args.capture)?))?;
+    records.sort_by_key(|record| record.ts);
+    log::info!("loaded {} records", records.len());
+
+    let first_ts = match records.first() {
+        Some(record) => record.ts,
+        None => return Ok(()),
+    };
+    if let TimestampMode::Shift = args.timestamps {
+        let offset = Utc::now() - first_ts;
+        records
+            .iter_mut()
+            .for_each(|record| record.shift_timestamps(offset));
+    }
+    let first_ts = records[0].ts;
+
+    let target = match &args.database_url {
+        Some(database_url) => Target::Dao(StatusDao::connect(database_url.clone()).await?),
+        None => Target::Http(RepuAggrClient::with_url(&args.url)?),
+    };
+
+    let start = Instant::now();
+    let (rate, speed) = (args.rate, args.speed);
+    let stats = stream::iter(records.into_iter().enumerate())
+        .then(|(idx, record)| async move {
+            let delay = match (rate, speed) {
+                (Some(rate), _) if rate > 0.0 => Duration::from_secs_f64(idx as f64 / rate),
+                (_, Some(speed)) if speed > 0.0 => (record.ts - first_ts)
+                    .to_std()
+                    .map(|d| d.div_f64(speed))
+                    .unwrap_or_default(),
+                _ => Duration::ZERO,
+            };
+            sleep_until(start + delay).await;
+            record
+        })
+        .map(|record| {
+            let target = &target;
+            async move {
+                let agreement_id = record.agreement_id.clone();
+                let result = target.send(record).await;
+                if let Err(e) = &result {
+                    log::error!("failed to replay {}: {:?}", agreement_id, e);
+                }
+                result
+            }
+        })
+        .buffer_unordered(args.concurrency.max(1))
+        .fold(Stats::default(), |mut stats, result| async move {
+            match result {
+                Ok(true) => stats.sent += 1,
+                Ok(false) => {
+                    stats.sent += 1;
+                    stats.unknown_agreement += 1;
+                }
+                Err(_) => stats.failed += 1,
+            }
+            stats
+        })
+        .await;
+
+    let elapsed = start.elapsed();
+    log::info!(
+        "sent {} requests ({} failed, {} unknown agreement) in {:.2}s, {:.1} req/s",
+        stats.sent,
+        stats.failed,
+        stats.unknown_agreement,
+        elapsed.as_secs_f64(),
+        (stats.sent + stats.failed) as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,456 @@
+use bigdecimal::{BigDecimal, ToPrimitive};
+use chrono::{DateTime, Duration, Utc};
+use futures::prelude::*;
+use rand::prelude::*;
+use reputation_aggregator_mock::capture::{CaptureRecord, CapturedRequest};
+use reputation_aggregator_mock::dao::{role_as_db, NewAuditEntry, ScoringAlgorithm, StatusDao};
+use reputation_aggregator_mock::events::EventHub;
+use reputation_aggregator_mock::scoring;
+use reputation_aggregator_model::{
+    AgreementInfoBuilder, AgreementRole, NodeId, RepuAggrClient, StatusBuilder,
+};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// Generates synthetic provider/requestor traffic and checks how well scoring
+/// separates honest nodes from misbehaving ones.
+#[derive(Debug, StructOpt)]
+struct Args {
+    #[structopt(long, env = "DATABASE_URL")]
+    /// Reputation database, used to refresh and read scores
+    database_url: String,
+    #[structopt(long)]
+    /// Send reports to reputation server instead of writing them into the database
+    url: Option<String>,
+    #[structopt(long, default_value = "50")]
+    providers: usize,
+    #[structopt(long, default_value = "20")]
+    requestors: usize,
+    #[structopt(long, default_value = "0.1")]
+    /// Fraction of requestors that accept but never pay
+    non_paying_requestors: f64,
+    #[structopt(long, default_value = "0.1")]
+    /// Fraction of providers that break agreements
+    breaking_providers: f64,
+    #[structopt(long, default_value = "2")]
+    /// Number of provider-requestor pairs reporting fake paid agreements to each other
+    colluding_pairs: usize,
+    #[structopt(long, default_value = "1000")]
+    /// Number of agreements between non-colluding nodes
+    agreements: usize,
+    #[structopt(long, default_value = "50")]
+    /// Number of agreements within each colluding pair
+    collusion_agreements: usize,
+    #[structopt(long)]
+    seed: Option<u64>,
+    #[structopt(long)]
+    /// Also write generated reports as JSONL capture (see `capture-replay`)
+    capture_out: Option<PathBuf>,
+    #[structopt(long, default_value = "8")]
+    /// Maximum number of reports in flight
+    concurrency: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Behavior {
+    Honest,
+    /// Requestor that accepts debit notes and invoices but never pays.
+    NonPaying,
+    /// Provider that terminates agreements before delivering what was requested.
+    Breaking,
+    /// Node that only trades with its colluding counterpart.
+    Colluding,
+}
+
+struct Node {
+    id: NodeId,
+    behavior: Behavior,
+}
+
+impl Node {
+    fn is_good(&self) -> bool {
+        self.behavior == Behavior::Honest
+    }
+}
+
+struct SimAgreement {
+    agreement_id: String,
+    provider: usize,
+    requestor: usize,
+    created_ts: DateTime<Utc>,
+    requested: i64,
+    accepted: i64,
+    confirmed: i64,
+}
+
+fn random_node_id(rng: &mut impl Rng) -> NodeId {
+    format!("0x{:032x}{:08x}", rng.gen::<u128>(), rng.gen::<u32>())
+        .parse()
+        .unwrap()
+}
+
+/// Amount in thousandths of GLM.
+fn amount(millis: i64) -> BigDecimal {
+    BigDecimal::from(millis) / BigDecimal::from(1000)
+}
+
+fn population(
+    rng: &mut impl Rng,
+    count: usize,
+    bad_fraction: f64,
+    bad_behavior: Behavior,
+    colluding: usize,
+) -> Vec<Node> {
+    let bad = (count as f64 * bad_fraction).round() as usize;
+    (0..count + colluding)
+        .map(|idx| Node {
+            id: random_node_id(rng),
+            behavior: if idx >= count {
+                Behavior::Colluding
+            } else if idx < bad {
+                bad_behavior
+            } else {
+                Behavior::Honest
+            },
+        })
+        .collect()
+}
+
+fn simulate(
+    rng: &mut impl Rng,
+    args: &Args,
+    providers: &[Node],
+    requestors: &[Node],
+) -> Vec<SimAgreement> {
+    let start = Utc::now() - Duration::days(30);
+    let mut agreements = Vec::new();
+    let mut new_agreement =
+        |rng: &mut dyn RngCore, provider, requestor, requested, accepted, confirmed| {
+            agreements.push(SimAgreement {
+                agreement_id: format!("{:032x}", rng.gen::<u128>()),
+                provider,
+                requestor,
+                created_ts: start + Duration::seconds(rng.gen_range(0..30 * 24 * 3600)),
+                requested,
+                accepted,
+                confirmed,
+            })
+        };
+
+    let honest_providers = args.providers;
+    let honest_requestors = args.requestors;
+    if honest_providers > 0 && honest_requestors > 0 {
+        for _ in 0..args.agreements {
+            let provider = rng.gen_range(0..honest_providers);
+            let requestor = rng.gen_range(0..honest_requestors);
+            let requested = rng.gen_range(100..10_000);
+            let accepted = match providers[provider].behavior {
+                Behavior::Breaking if rng.gen_bool(0.3) => 0,
+                Behavior::Breaking => requested * rng.gen_range(10..50) / 100,
+                _ => requested,
+            };
+            let confirmed = match requestors[requestor].behavior {
+                Behavior::NonPaying => 0,
+                _ => accepted,
+            };
+            new_agreement(rng, provider, requestor, requested, accepted, confirmed);
+        }
+    }
+
+    for pair in 0..args.colluding_pairs {
+        for _ in 0..args.collusion_agreements {
+            let requested = rng.gen_range(5_000..20_000);
+            new_agreement(
+                rng,
+                honest_providers + pair,
+                honest_requestors + pair,
+                requested,
+                requested,
+                requested,
+            );
+        }
+    }
+    agreements
+}
+
+fn capture_records(
+    agreement: &SimAgreement,
+    providers: &[Node],
+    requestors: &[Node],
+) -> Result<Vec<CaptureRecord>, Box<dyn Error>> {
+    let provider_id = providers[agreement.provider].id;
+    let requestor_id = requestors[agreement.requestor].id;
+    let reported_ts = agreement.created_ts + Duration::hours(1);
+    let status = StatusBuilder::default()
+        .requested(amount(agreement.requested))
+        .accepted(amount(agreement.accepted))
+        .confirmed(amount(agreement.confirmed))
+        .ts(reported_ts)
+        .build()?;
+
+    let mut records = Vec::with_capacity(4);
+    for (role, node_id, peer_id) in [
+        (AgreementRole::Provider, provider_id, requestor_id),
+        (AgreementRole::Requestor, requestor_id, provider_id),
+    ] {
+        let info = AgreementInfoBuilder::default()
+            .peer_id(peer_id)
+            .created_ts(agreement.created_ts)
+            .valid_to(Some(agreement.created_ts + Duration::hours(1)))
+            .runtime(Some("vm".to_string()))
+            .payment_platform("erc20-polygon-glm")
+            .payment_address(provider_id.to_string())
+            .subnet(Some("public".to_string()))
+            .task_package(None::<String>)
+            .build()?;
+        records.push(CaptureRecord {
+            ts: agreement.created_ts,
+            role,
+            node_id,
+            agreement_id: agreement.agreement_id.clone(),
+            request: CapturedRequest::Agreement(info),
+        });
+        records.push(CaptureRecord {
+            ts: reported_ts,
+            role,
+            node_id,
+            agreement_id: agreement.agreement_id.clone(),
+            request: CapturedRequest::Status(status.clone()),
+        });
+    }
+    Ok(records)
+}
+
+enum Target<'a> {
+    Http(RepuAggrClient),
+    Dao(&'a StatusDao),
+}
+
+impl Target<'_> {
+    async fn send(&self, record: CaptureRecord) -> Result<(), Box<dyn Error>> {
+        let role = record.role;
+        let target = format!(
+            "{}/{}/{}",
+            role_as_db(role),
+            record.node_id,
+            record.agreement_id
+        );
+        match (self, record.request) {
+            (Target::Http(client), CapturedRequest::Agreement(info)) => {
+                client
+                    .agreement(role, record.node_id, &record.agreement_id, info)
+                    .await?
+            }
+            (Target::Http(client), CapturedRequest::Status(status)) => {
+                client
+                    .report(role, record.node_id, &record.agreement_id, status)
+                    .await?;
+            }
+            (Target::Dao(dao), CapturedRequest::Agreement(info)) => {
+                let audit_entry =
+                    NewAuditEntry::cli("saveAgreementDetails", &target).with_payload(&info)?;
+                dao.insert_agreement(
+                    role_as_db(role),
+                    record.node_id,
+                    &record.agreement_id,
+                    info,
+                    &audit_entry,
+                )
+                .await?;
+            }
+            (Target::Dao(dao), CapturedRequest::Status(status)) => {
+                let audit_entry =
+                    NewAuditEntry::cli("saveAgreementStatus", &target).with_payload(&status)?;
+                dao.insert_status(
+                    role_as_db(role),
+                    record.node_id,
+                    &record.agreement_id,
+                    &status,
+                    &audit_entry,
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Scoring algorithm under evaluation.
+#[derive(Debug, Clone, Copy)]
+enum Algorithm {
+    Standard,
+    Trust,
+}
+
+impl Algorithm {
+    const ALL: &'static [Algorithm] = &[Algorithm::Standard, Algorithm::Trust];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Standard => "standard",
+            Algorithm::Trust => "trust",
+        }
+    }
+
+    async fn score(
+        &self,
+        dao: &StatusDao,
+        role: AgreementRole,
+        node_id: NodeId,
+    ) -> Result<Option<f64>, Box<dyn Error>> {
+        let algorithm = match self {
+            Algorithm::Standard => ScoringAlgorithm::Standard,
+            Algorithm::Trust => ScoringAlgorithm::Trust,
+        };
+        Ok(dao
+            .standard_score(role_as_db(role), &node_id.to_string(), algorithm, None)
+            .await?
+            .score
+            .and_then(|score| score.to_f64()))
+    }
+}
+
+struct Separation {
+    good: Vec<f64>,
+    bad: Vec<f64>,
+}
+
+impl Separation {
+    fn mean(values: &[f64]) -> f64 {
+        values.iter().sum::<f64>() / values.len().max(1) as f64
+    }
+
+    /// Probability that a random good node scores higher than a random bad one.
+    fn auc(&self) -> Option<f64> {
+        if self.good.is_empty() || self.bad.is_empty() {
+            return None;
+        }
+        let wins: f64 = self
+            .good
+            .iter()
+            .flat_map(|good| {
+                self.bad.iter().map(move |bad| match good.partial_cmp(bad) {
+                    Some(std::cmp::Ordering::Greater) => 1.0,
+                    Some(std::cmp::Ordering::Equal) => 0.5,
+                    _ => 0.0,
+                })
+            })
+            .sum();
+        Some(wins / (self.good.len() * self.bad.len()) as f64)
+    }
+}
+
+async fn evaluate(
+    dao: &StatusDao,
+    algorithm: Algorithm,
+    role: AgreementRole,
+    nodes: &[Node],
+) -> Result<Separation, Box<dyn Error>> {
+    let mut separation = Separation {
+        good: Vec::new(),
+        bad: Vec::new(),
+    };
+    for node in nodes {
+        if let Some(score) = algorithm.score(dao, role, node.id).await? {
+            if node.is_good() {
+                separation.good.push(score)
+            } else {
+                separation.bad.push(score)
+            }
+        }
+    }
+    Ok(separation)
+}
+
+#[actix_rt::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::from_args();
+    env_logger::init();
+
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let providers = population(
+        &mut rng,
+        args.providers,
+        args.breaking_providers,
+        Behavior::Breaking,
+        args.colluding_pairs,
+    );
+    let requestors = population(
+        &mut rng,
+        args.requestors,
+        args.non_paying_requestors,
+        Behavior::NonPaying,
+        args.colluding_pairs,
+    );
+    let agreements = simulate(&mut rng, &args, &providers, &requestors);
+    let mut records = Vec::with_capacity(agreements.len() * 4);
+    for agreement in &agreements {
+        records.extend(capture_records(agreement, &providers, &requestors)?);
+    }
+    records.sort_by_key(|record| record.ts);
+    log::info!(
+        "generated {} agreements, {} reports",
+        agreements.len(),
+        records.len()
+    );
+
+    if let Some(path) = &args.capture_out {
+        let mut out = BufWriter::new(File::create(path)?);
+        for record in &records {
+            serde_json::to_writer(&mut out, record)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()?;
+    }
+
+    let dao = StatusDao::connect(args.database_url.clone()).await?;
+    let target = match &args.url {
+        Some(url) => Target::Http(RepuAggrClient::with_url(url)?),
+        None => Target::Dao(&dao),
+    };
+    let failed = stream::iter(records)
+        .map(|record| target.send(record))
+        .buffer_unordered(args.concurrency.max(1))
+        .filter(|result| future::ready(result.is_err()))
+        .count()
+        .await;
+    if failed > 0 {
+        log::warn!("{} reports failed", failed);
+    }
+
+    scoring::refresh(&dao, &EventHub::default()).await?;
+
+    println!(
+        "{:<12} {:<10} {:>6} {:>6} {:>12} {:>12} {:>8}",
+        "algorithm", "role", "good", "bad", "mean(good)", "mean(bad)", "auc"
+    );
+    for algorithm in Algorithm::ALL {
+        for (role, nodes) in [
+            (AgreementRole::Provider, &providers),
+            (AgreementRole::Requestor, &requestors),
+        ] {
+            let separation = evaluate(&dao, *algorithm, role, nodes).await?;
+            println!(
+                "{:<12} {:<10} {:>6} {:>6} {:>12.4} {:>12.4} {:>8}",
+                algorithm.name(),
+                format!("{:?}", role).to_lowercase(),
+                separation.good.len(),
+                separation.bad.len(),
+                Separation::mean(&separation.good),
+                Separation::mean(&separation.bad),
+                separation
+                    .auc()
+                    .map(|auc| format!("{:.3}", auc))
+                    .unwrap_or_else(|| "-".to_string())
+            );
+        }
+    }
+
+    Ok(())
+}
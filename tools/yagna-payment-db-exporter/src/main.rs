@@ -1,10 +1,11 @@
-use chrono::{DateTime, NaiveDateTime};
+use chrono::NaiveDateTime;
 use futures::prelude::*;
 use reputation_aggregator_model::*;
 use sqlx::sqlite::SqliteConnectOptions;
-use sqlx::types::chrono::Utc;
+use sqlx::types::chrono::{TimeZone, Utc};
 use sqlx::types::BigDecimal;
 use sqlx::{Connection, Sqlite, SqliteConnection, FromRow};
+use std::collections::HashMap;
 use std::error::Error;
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -14,12 +15,17 @@ struct Args {
     #[structopt(long)]
     /// Database with payments
     payment_database: Option<PathBuf>,
-    /// Market database
+    /// Market database, not read yet
+    #[allow(dead_code)]
     market_database: Option<PathBuf>,
     #[structopt(long, conflicts_with_all=&["payment_database", "market_database"])]
+    #[allow(dead_code)]
     data_dir : Option<PathBuf>,
     #[structopt(long, default_value = "http://reputation.dev.golem.network")]
     url: String,
+    #[structopt(long)]
+    /// Do not send debit note, invoice and payment events
+    skip_documents: bool,
 }
 
 fn role_from_db(db_role_id:&str) -> Option<AgreementRole> {
@@ -30,6 +36,111 @@ fn role_from_db(db_role_id:&str) -> Option<AgreementRole> {
     })
 }
 
+fn document_type_from_db(db_document_type: &str) -> Option<DocumentType> {
+    Some(match db_document_type {
+        "DEBIT_NOTE" => DocumentType::DebitNote,
+        "INVOICE" => DocumentType::Invoice,
+        "PAYMENT" => DocumentType::Payment,
+        _ => return None,
+    })
+}
+
+fn event_type_from_db(db_event_type: &str) -> Option<DocumentEventType> {
+    Some(match db_event_type {
+        "ISSUED" => DocumentEventType::Issued,
+        "ACCEPTED" => DocumentEventType::Accepted,
+        "REJECTED" => DocumentEventType::Rejected,
+        "SETTLED" => DocumentEventType::Settled,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, FromRow)]
+struct DocumentEventRow {
+    owner_id: String,
+    agreement_id: String,
+    document_id: String,
+    document_type: String,
+    event_type: String,
+    amount: Option<String>,
+    ts: NaiveDateTime,
+}
+
+/// Loads debit note, invoice and payment history grouped by (owner_id, agreement_id).
+async fn load_document_events(
+    connection: &mut SqliteConnection,
+) -> std::result::Result<HashMap<(String, String), Vec<DocumentEvent>>, Box<dyn Error>> {
+    let rows: Vec<DocumentEventRow> = sqlx::query_as::<Sqlite, DocumentEventRow>(
+        r#"
+        SELECT dn.owner_id, a.agreement_id, dn.id AS document_id,
+            'DEBIT_NOTE' AS document_type, 'ISSUED' AS event_type,
+            dn.total_amount_due AS amount, dn.timestamp AS ts
+        FROM pay_debit_note dn
+        JOIN pay_activity a ON a.id = dn.activity_id AND a.owner_id = dn.owner_id
+        UNION ALL
+        SELECT e.owner_id, a.agreement_id, dn.id,
+            'DEBIT_NOTE', e.event_type,
+            dn.total_amount_due, e.timestamp
+        FROM pay_debit_note_event e
+        JOIN pay_debit_note dn ON dn.id = e.debit_note_id AND dn.owner_id = e.owner_id
+        JOIN pay_activity a ON a.id = dn.activity_id AND a.owner_id = dn.owner_id
+        UNION ALL
+        SELECT i.owner_id, i.agreement_id, i.id,
+            'INVOICE', 'ISSUED',
+            i.amount, i.timestamp
+        FROM pay_invoice i
+        UNION ALL
+        SELECT e.owner_id, i.agreement_id, i.id,
+            'INVOICE', e.event_type,
+            i.amount, e.timestamp
+        FROM pay_invoice_event e
+        JOIN pay_invoice i ON i.id = e.invoice_id AND i.owner_id = e.owner_id
+        UNION ALL
+        SELECT p.owner_id, ap.agreement_id, p.id,
+            'PAYMENT', 'SETTLED',
+            ap.amount, p.timestamp
+        FROM pay_agreement_payment ap
+        JOIN pay_payment p ON p.id = ap.payment_id AND p.owner_id = ap.owner_id
+        UNION ALL
+        SELECT p.owner_id, a.agreement_id, p.id,
+            'PAYMENT', 'SETTLED',
+            ap.amount, p.timestamp
+        FROM pay_activity_payment ap
+        JOIN pay_payment p ON p.id = ap.payment_id AND p.owner_id = ap.owner_id
+        JOIN pay_activity a ON a.id = ap.activity_id AND a.owner_id = ap.owner_id
+        "#,
+    )
+    .fetch_all(connection)
+    .await?;
+
+    let mut events: HashMap<(String, String), Vec<DocumentEvent>> = HashMap::new();
+    for row in rows {
+        let (document_type, event_type) = match (
+            document_type_from_db(&row.document_type),
+            event_type_from_db(&row.event_type),
+        ) {
+            (Some(document_type), Some(event_type)) => (document_type, event_type),
+            _ => continue,
+        };
+        let amount = row.amount.as_deref().map(str::parse::<BigDecimal>).transpose()?;
+        let event = DocumentEventBuilder::default()
+            .document_id(row.document_id)
+            .document_type(document_type)
+            .event_type(event_type)
+            .amount(amount)
+            .ts(Utc.from_utc_datetime(&row.ts))
+            .build()?;
+        events
+            .entry((row.owner_id, row.agreement_id))
+            .or_default()
+            .push(event);
+    }
+    for agreement_events in events.values_mut() {
+        agreement_events.sort_by_key(|event| event.ts);
+    }
+    Ok(events)
+}
+
 #[actix_rt::main]
 async fn main() -> std::result::Result<(), Box<dyn Error>> {
     let args = Args::from_args();
@@ -72,10 +183,23 @@ async fn main() -> std::result::Result<(), Box<dyn Error>> {
     .fetch_all(&mut connection)
     .await?;
 
+    let mut document_events = if args.skip_documents {
+        HashMap::new()
+    } else {
+        load_document_events(&mut connection).await?
+    };
+    log::info!("loaded payment documents for {} agreements", document_events.len());
+
     let client = RepuAggrClient::with_url(&args.url)?;
 
     let _ = stream::iter(agreements)
         .map(|agreement| {
+            let payment = document_events
+                .remove(&(agreement.owner_id.clone(), agreement.id.clone()))
+                .map(PaymentDetails::from);
+            (agreement, payment)
+        })
+        .map(|(agreement, payment)| {
             let client = &client;
             async move {
                 log::info!("sending: {}", agreement.id);
@@ -84,25 +208,23 @@ async fn main() -> std::result::Result<(), Box<dyn Error>> {
                 let paid: BigDecimal = agreement.total_amount_paid.parse()?;
                 let ts = agreement
                     .updated_ts
-                    .map(|ts| DateTime::from_utc(ts, Utc))
-                    .unwrap_or_else(|| Utc::now());
+                    .map(|ts| Utc.from_utc_datetime(&ts))
+                    .unwrap_or_else(Utc::now);
 
                 let status = StatusBuilder::default()
                     .requested(requested)
                     .accepted(accepted)
                     .confirmed(paid)
                     .ts(ts)
+                    .payment(payment)
                     .build()?;
                 let role = role_from_db(&agreement.role).unwrap();
-                let peer_id : NodeId = agreement.peer_id.parse()?;
+                let _peer_id : NodeId = agreement.peer_id.parse()?;
 
-                match client.report(role,  agreement.owner_id.parse()?,
+                if let ReportResult::UnknownAgreement {} = client.report(role,  agreement.owner_id.parse()?,
                               &agreement.id,
                               status).await? {
-                    ReportResult::UnknownAgreement {} => {
-                        log::warn!("missing data for: {}", agreement.id)
-                    }
-                    _ => ()
+                    log::warn!("missing data for: {}", agreement.id)
                 }
                 Ok::<_, Box<dyn Error>>(())
             }
@@ -1,15 +1,93 @@
 import React, {useEffect, useState} from 'react';
 import 'bootstrap/scss/bootstrap.scss';
 
+interface StandardScore {
+    score?: string;
+    status: 'ok' | 'insufficientData' | 'notScored' | 'banned';
+    rank?: number;
+    percentile?: number;
+    agreements: number;
+    counterparties: number;
+    confidenceInterval?: { lower: number, upper: number };
+    overridden: boolean;
+}
+
+interface NodeProfile {
+    nodeId: string;
+    agreements: number;
+    classifications: { [classification: string]: number };
+    requested: string;
+    accepted: string;
+    confirmed: string;
+    counterparties: number;
+    firstSeen?: string;
+    lastSeen?: string;
+    paymentPlatforms: string[];
+    runtimes: string[];
+    score: StandardScore;
+}
+
+function formatScore(score : StandardScore) {
+    switch (score.status) {
+        case 'ok':
+            return `${score.score} (#${score.rank}, percentile ${score.percentile?.toFixed(1)})`
+                + (score.overridden ? ' set by operator' : '');
+        case 'insufficientData':
+            return `insufficient data (${score.agreements} agreements, ${score.counterparties} counterparties)`;
+        case 'banned':
+            return 'banned';
+        default:
+            return 'n/a';
+    }
+}
+
+function roleOf(tab : string) {
+    return tab == 'p' ? 'provider' : 'requestor';
+}
+
+function Profile({profile} : { profile: NodeProfile }) {
+    return (<div className="card mb-3">
+        <div className="card-header">{profile.nodeId}</div>
+        <div className="card-body">
+            <dl className="row mb-0">
+                <dt className="col-3">Score</dt>
+                <dd className="col-9">{formatScore(profile.score)}</dd>
+                <dt className="col-3">Agreements</dt>
+                <dd className="col-9">{profile.agreements} with {profile.counterparties} counterparties</dd>
+                <dt className="col-3">Classifications</dt>
+                <dd className="col-9">{Object.entries(profile.classifications)
+                    .map(([classification, count]) => `${classification}: ${count}`).join(', ')}</dd>
+                <dt className="col-3">Requested / accepted / confirmed</dt>
+                <dd className="col-9">{profile.requested} / {profile.accepted} / {profile.confirmed}</dd>
+                <dt className="col-3">Seen</dt>
+                <dd className="col-9">{profile.firstSeen} &ndash; {profile.lastSeen}</dd>
+                <dt className="col-3">Payment platforms</dt>
+                <dd className="col-9">{profile.paymentPlatforms.join(', ')}</dd>
+                <dt className="col-3">Runtimes</dt>
+                <dd className="col-9">{profile.runtimes.join(', ')}</dd>
+            </dl>
+        </div>
+    </div>);
+}
+
 export function App() {
     const [tab, setTab] = useState('p');
     const [nodes, setNodes] = useState([]);
+    const [profile, setProfile] = useState<NodeProfile | null>(null);
 
     async function fetchData(tab : string) {
         console.log('tab', tab);
-        let resp = await fetch(tab == 'p' ? "/provider" : "/requestor");
+        let resp = await fetch(`/api/v1/${roleOf(tab)}`);
         let json = await resp.json();
         setNodes(json);
+        setProfile(null);
+    }
+
+    async function fetchProfile(node : string) {
+        let resp = await fetch(`/api/v1/${roleOf(tab)}/${node}`);
+        if (resp.ok) {
+            setProfile(await resp.json());
+        }
     }
 
     useEffect(() => {
@@ -26,6 +104,7 @@ export function App() {
                 <a className={tab === 'r' ? 'nav-link active' : 'nav-link'} onClick={() => setTab('r')} href="#">Requestors</a>
             </li>
         </ul>
+        {profile && <Profile profile={profile}/>}
         <div className="row">
             {nodes.map((node) => <div className="col-4 p-1">
                 <div key={node} className="card">
@@ -33,7 +112,7 @@ export function App() {
                 <div className="card-body">
                     <div className="card-title">Show me</div>
                     <div className="card-text"></div>
-                    <a href="#" className="btn btn-primary">details</a>
+                    <a href="#" className="btn btn-primary" onClick={() => fetchProfile(node)}>details</a>
                 </div>
                 </div>
             </div>)}